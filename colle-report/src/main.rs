@@ -0,0 +1,113 @@
+//! Looks up every question of a colle program and writes a report with the best page for each
+//! one, to check the program against the lessons without typing each question in the search.
+//!
+//! Configured with environment variables:
+//! - `INDEX_FILE`: the search index, `db/search-index.bin` by default.
+//! - `QUESTIONS_FILE`: the program, one question per line, `colle.txt` by default. Empty lines
+//!   and list markers such as `-` or `1.` are ignored.
+//! - `REPORT_FILE`: where to write the report, `colle-report.md` by default. The report is in
+//!   HTML if the file name ends with `.html`, and in Markdown otherwise.
+//! - `SYNONYMS_FILE`: the synonyms that the index was built with, the built-in ones by default.
+//! - `QUERY_LANGUAGE`: the language of the questions, French by default.
+
+use std::{env, fs, io, path::PathBuf, sync::Arc};
+
+use search_index::{
+    mapped::MappedSearchIndex,
+    normalize::Language,
+    search::{search_with_options, SearchOptions},
+    synonyms::Synonyms,
+};
+
+use crate::report::{write_report, Entry, Format};
+
+mod report;
+
+/// Removes list markers such as `-`, `•` or `12.` from the start of a line.
+fn strip_list_marker(line: &str) -> &str {
+    let line = line.trim();
+    let line = line.trim_start_matches(['-', '*', '•']);
+    let digits = line.chars().take_while(|c| c.is_ascii_digit()).count();
+    let line = match line[digits..].strip_prefix(['.', ')']) {
+        Some(rest) if digits > 0 => rest,
+        _ => line,
+    };
+    line.trim()
+}
+
+fn main() -> io::Result<()> {
+    let index_path = env::var("INDEX_FILE").unwrap_or_else(|_| "db/search-index.bin".to_owned());
+    let questions_path: PathBuf = env::var_os("QUESTIONS_FILE")
+        .unwrap_or_else(|| "colle.txt".into())
+        .into();
+    let report_path: PathBuf = env::var_os("REPORT_FILE")
+        .unwrap_or_else(|| "colle-report.md".into())
+        .into();
+
+    let search_index = MappedSearchIndex::open(&index_path)
+        .unwrap_or_else(|e| panic!("failed to load search index at {}: {}", index_path, e));
+
+    let synonyms = match env::var_os("SYNONYMS_FILE") {
+        Some(path) => Arc::new(Synonyms::load(&path).unwrap_or_else(|e| {
+            panic!("failed to load synonyms at {}: {}", path.to_string_lossy(), e)
+        })),
+        None => Synonyms::builtin(),
+    };
+    if let Some(mismatch) = search_index.synonyms_mismatch(&synonyms) {
+        eprintln!("warning: {}", mismatch);
+    }
+    let language = match env::var("QUERY_LANGUAGE") {
+        Ok(language) => Language::parse(&language)
+            .unwrap_or_else(|| panic!("unknown language {:?}", language)),
+        Err(_) => Language::default(),
+    };
+
+    let options = SearchOptions {
+        limit: 1,
+        synonyms,
+        expand_synonyms: search_index.has_query_synonyms(),
+        language,
+        ..Default::default()
+    };
+    let entries: Vec<Entry> = fs::read_to_string(&questions_path)?
+        .lines()
+        .map(strip_list_marker)
+        .filter(|q| !q.is_empty())
+        .map(|question| Entry {
+            question: question.to_owned(),
+            page: search_with_options(&search_index, question, &options)
+                .pages
+                .into_iter()
+                .next(),
+        })
+        .collect();
+
+    let format = match report_path.extension() {
+        Some(ext) if ext == "html" => Format::Html,
+        _ => Format::Markdown,
+    };
+    fs::write(&report_path, write_report(&entries, format))?;
+    eprintln!(
+        "Wrote the best pages for {} questions to {}",
+        entries.len(),
+        report_path.display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::strip_list_marker;
+
+    #[test]
+    fn list_markers() {
+        assert_eq!(
+            strip_list_marker("  - Loi faible des grands nombres."),
+            "Loi faible des grands nombres."
+        );
+        assert_eq!(strip_list_marker("12. Théorème de Rolle"), "Théorème de Rolle");
+        assert_eq!(strip_list_marker("3) Lemme"), "Lemme");
+        assert_eq!(strip_list_marker("• Inégalité"), "Inégalité");
+        assert_eq!(strip_list_marker("2022 en bref"), "2022 en bref");
+    }
+}
@@ -0,0 +1,194 @@
+//! Formatting of the report, as Markdown or HTML.
+
+use std::fmt::Write;
+
+use search_index::search::MatchPage;
+
+pub(crate) enum Format {
+    Markdown,
+    Html,
+}
+
+/// A question of the colle program and the best page found for it, if any.
+pub(crate) struct Entry {
+    pub question: String,
+    pub page: Option<MatchPage>,
+}
+
+fn escape_html(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn escape_markdown(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if "\\`*_[]<>|#".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn write_markdown(entries: &[Entry]) -> String {
+    let mut out = String::from("# Programme de colle\n");
+    for (i, entry) in entries.iter().enumerate() {
+        write!(out, "\n## {}. {}\n\n", i + 1, escape_markdown(&entry.question)).unwrap();
+        let page = match &entry.page {
+            Some(p) => p,
+            None => {
+                out.push_str("Aucune page trouvée.\n");
+                continue;
+            }
+        };
+        writeln!(
+            out,
+            "- Document : {}, page {}",
+            escape_markdown(&page.document_digest),
+            page.number + 1
+        )
+        .unwrap();
+        if !page.breadcrumb.is_empty() {
+            let breadcrumb: Vec<_> = page.breadcrumb.iter().map(|t| escape_markdown(t)).collect();
+            writeln!(out, "- Section : {}", breadcrumb.join(" › ")).unwrap();
+        }
+        writeln!(
+            out,
+            "- Rendu : `{}.avif`, `{}.jpg` ({}×{})",
+            page.rendered_avif, page.rendered_jpeg, page.width, page.height
+        )
+        .unwrap();
+        out.push_str("- Surlignages :");
+        if page.highlights.is_empty() {
+            out.push_str(" aucun");
+        }
+        for h in page.highlights.iter() {
+            write!(out, " ({}, {}, {}×{})", h.x, h.y, h.width, h.height).unwrap();
+        }
+        out.push('\n');
+    }
+    out
+}
+
+fn write_html(entries: &[Entry]) -> String {
+    let mut out = String::from(
+        "<!DOCTYPE html>\n<html lang=\"fr\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>Programme de colle</title>\n</head>\n<body>\n<h1>Programme de colle</h1>\n<ol>\n",
+    );
+    for entry in entries.iter() {
+        write!(out, "<li>\n<h2>{}</h2>\n", escape_html(&entry.question)).unwrap();
+        let page = match &entry.page {
+            Some(p) => p,
+            None => {
+                out.push_str("<p>Aucune page trouvée.</p>\n</li>\n");
+                continue;
+            }
+        };
+        write!(
+            out,
+            "<p>{}, page {}</p>\n<p>Rendu : <code>{}.avif</code>, <code>{}.jpg</code> ({}×{})</p>\n",
+            escape_html(&page.document_digest),
+            page.number + 1,
+            escape_html(&page.rendered_avif),
+            escape_html(&page.rendered_jpeg),
+            page.width,
+            page.height
+        )
+        .unwrap();
+        if !page.breadcrumb.is_empty() {
+            let breadcrumb: Vec<_> = page.breadcrumb.iter().map(|t| escape_html(t)).collect();
+            writeln!(out, "<p>Section : {}</p>", breadcrumb.join(" › ")).unwrap();
+        }
+        out.push_str("<table>\n<tr><th>x</th><th>y</th><th>largeur</th><th>hauteur</th></tr>\n");
+        for h in page.highlights.iter() {
+            writeln!(
+                out,
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                h.x, h.y, h.width, h.height
+            )
+            .unwrap();
+        }
+        out.push_str("</table>\n</li>\n");
+    }
+    out.push_str("</ol>\n</body>\n</html>\n");
+    out
+}
+
+pub(crate) fn write_report(entries: &[Entry], format: Format) -> String {
+    match format {
+        Format::Markdown => write_markdown(entries),
+        Format::Html => write_html(entries),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use search_index::search::{Highlight, MatchPage};
+
+    use super::{write_report, Entry, Format};
+
+    fn entries() -> Vec<Entry> {
+        vec![
+            Entry {
+                question: "Inégalité de Cauchy-Schwarz <x|y>".to_owned(),
+                page: Some(MatchPage {
+                    document_digest: "23_rev_Espaces_prehilbertiens.pdf".to_owned(),
+                    number: 3,
+                    rendered_avif: "abc".to_owned(),
+                    rendered_jpeg: "def".to_owned(),
+                    width: 1000,
+                    height: 1400,
+                    highlights: vec![Highlight {
+                        x: 10,
+                        y: 20,
+                        width: 300,
+                        height: 15,
+                        terms: vec!["inegalite".to_owned()],
+                    }],
+                    term_hits: Vec::new(),
+                    snippets: Vec::new(),
+                    breadcrumb: vec![
+                        "Espaces préhilbertiens".to_owned(),
+                        "Produit scalaire".to_owned(),
+                    ],
+                    hotspot: None,
+                    explanation: None,
+                }),
+            },
+            Entry {
+                question: "Théorème introuvable".to_owned(),
+                page: None,
+            },
+        ]
+    }
+
+    #[test]
+    fn markdown() {
+        let report = write_report(&entries(), Format::Markdown);
+        assert!(report.contains("## 1. Inégalité de Cauchy-Schwarz \\<x\\|y\\>\n"));
+        assert!(report.contains("- Document : 23\\_rev\\_Espaces\\_prehilbertiens.pdf, page 4\n"));
+        assert!(report.contains("- Section : Espaces préhilbertiens › Produit scalaire\n"));
+        assert!(report.contains("- Surlignages : (10, 20, 300×15)\n"));
+        assert!(report.contains("## 2. Théorème introuvable\n\nAucune page trouvée.\n"));
+    }
+
+    #[test]
+    fn html() {
+        let report = write_report(&entries(), Format::Html);
+        assert!(report.contains("<h2>Inégalité de Cauchy-Schwarz &lt;x|y&gt;</h2>"));
+        assert!(report.contains("<code>abc.avif</code>"));
+        assert!(report.contains("<p>Section : Espaces préhilbertiens › Produit scalaire</p>"));
+        assert!(report.contains("<tr><td>10</td><td>20</td><td>300</td><td>15</td></tr>"));
+        assert!(report.ends_with("</html>\n"));
+    }
+}
@@ -0,0 +1,106 @@
+//! Relevance judgments: which pages are expected for a query, and how relevant they are.
+//!
+//! A judgment file is a JSON array of judgments such as
+//!
+//! ```json
+//! [
+//!     {
+//!         "query": "Inégalités de Bienaymé-Tchebychev.",
+//!         "document": "20_Variables_Aleatoires.pdf",
+//!         "pages": [36],
+//!         "grade": 3
+//!     }
+//! ]
+//! ```
+//!
+//! Pages are numbered from 0, like `MatchPage::number`. The grade defaults to 1, and higher grades
+//! mark more relevant pages. A query can have several judgments, e.g. a grade 3 for the page of
+//! the theorem and a grade 1 for the page of its proof.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    io::{self, BufReader},
+    path::Path,
+};
+
+use serde::Deserialize;
+
+fn one() -> u32 {
+    1
+}
+
+#[derive(Deserialize)]
+pub(crate) struct Judgment {
+    pub query: String,
+    pub document: String,
+    pub pages: Vec<u16>,
+    #[serde(default = "one")]
+    pub grade: u32,
+}
+
+/// The judgments of a single query.
+#[derive(Default)]
+pub(crate) struct QueryJudgments {
+    grades: HashMap<(String, u16), u32>,
+}
+
+impl QueryJudgments {
+    /// Returns the grade of a page, 0 if it was not judged relevant.
+    pub fn grade(&self, document: &str, page: u16) -> u32 {
+        self.grades
+            .get(&(document.to_owned(), page))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Returns the grades of all the relevant pages, best first.
+    pub fn ideal_grades(&self) -> Vec<u32> {
+        let mut grades: Vec<_> = self.grades.values().copied().collect();
+        grades.sort_unstable_by(|a, b| b.cmp(a));
+        grades
+    }
+}
+
+/// Groups judgments by query. A page that is judged several times for the same query keeps its
+/// best grade.
+pub(crate) fn group_by_query(judgments: Vec<Judgment>) -> BTreeMap<String, QueryJudgments> {
+    let mut queries: BTreeMap<String, QueryJudgments> = BTreeMap::new();
+    for j in judgments {
+        let query = queries.entry(j.query).or_default();
+        for page in j.pages {
+            let grade = query.grades.entry((j.document.clone(), page)).or_default();
+            *grade = (*grade).max(j.grade);
+        }
+    }
+    queries
+}
+
+pub(crate) fn load(path: &Path) -> io::Result<BTreeMap<String, QueryJudgments>> {
+    let judgments: Vec<Judgment> = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+    Ok(group_by_query(judgments))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{group_by_query, Judgment};
+
+    #[test]
+    fn grouping() {
+        let judgments: Vec<Judgment> = serde_json::from_str(
+            r#"[
+                {"query": "loi faible", "document": "a.pdf", "pages": [3, 4], "grade": 3},
+                {"query": "loi faible", "document": "a.pdf", "pages": [4, 5]},
+                {"query": "poisson", "document": "b.pdf", "pages": [1]}
+            ]"#,
+        )
+        .unwrap();
+        let queries = group_by_query(judgments);
+        assert_eq!(queries.len(), 2);
+        let loi_faible = &queries["loi faible"];
+        assert_eq!(loi_faible.grade("a.pdf", 4), 3);
+        assert_eq!(loi_faible.grade("a.pdf", 5), 1);
+        assert_eq!(loi_faible.grade("b.pdf", 1), 0);
+        assert_eq!(loi_faible.ideal_grades(), vec![3, 3, 1]);
+    }
+}
@@ -0,0 +1,223 @@
+//! Runs the queries of a relevance judgment file against a search index and reports how well the
+//! expected pages are ranked, optionally compared to a saved run.
+//!
+//! Configured with environment variables:
+//! - `INDEX_FILE`: the search index, `db/search-index.bin` by default.
+//! - `JUDGMENTS_FILE`: the judgments, `judgments.json` by default. See the `judgments` module.
+//! - `K`: the number of pages that are looked at for each query, 5 by default like the server.
+//! - `SCORING`: `raw` (the default) or `bm25`, see `search::Scoring`.
+//! - `SYNONYMS_FILE`: the synonyms that the index was built with, the built-in ones by default.
+//! - `QUERY_LANGUAGE`: the language of the queries, French by default.
+//! - `TILE_SIZE` and `HOTSPOT_RADIUS`: override the defaults of `SearchOptions`, to tune them.
+//! - `BASELINE_FILE`: a run saved with `RUN_FILE` to compare to.
+//! - `RUN_FILE`: where to save this run.
+
+use std::{
+    env,
+    fs::File,
+    io::{self, BufReader, BufWriter},
+    sync::Arc,
+};
+
+use search_index::{
+    mapped::MappedSearchIndex,
+    normalize::Language,
+    search::{search_with_options, Scoring, SearchOptions},
+    synonyms::Synonyms,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::judgments::QueryJudgments;
+
+mod judgments;
+mod metrics;
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct QueryRun {
+    query: String,
+    /// The returned pages, as `document#page`.
+    pages: Vec<String>,
+    reciprocal_rank: f32,
+    precision: f32,
+    ndcg: f32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Run {
+    k: usize,
+    queries: Vec<QueryRun>,
+}
+
+impl Run {
+    /// Returns the mean reciprocal rank, precision and NDCG.
+    fn means(&self) -> (f32, f32, f32) {
+        let n = self.queries.len().max(1) as f32;
+        let sum = |metric: fn(&QueryRun) -> f32| self.queries.iter().map(metric).sum::<f32>() / n;
+        (
+            sum(|q| q.reciprocal_rank),
+            sum(|q| q.precision),
+            sum(|q| q.ndcg),
+        )
+    }
+}
+
+fn evaluate_query(
+    search_index: &MappedSearchIndex,
+    query: &str,
+    judgments: &QueryJudgments,
+    options: &SearchOptions,
+) -> QueryRun {
+    let k = options.limit;
+    let pages = search_with_options(search_index, query, options).pages;
+    let grades: Vec<u32> = pages
+        .iter()
+        .map(|p| judgments.grade(&p.document_digest, p.number))
+        .collect();
+    QueryRun {
+        query: query.to_owned(),
+        pages: pages
+            .iter()
+            .map(|p| format!("{}#{}", p.document_digest, p.number))
+            .collect(),
+        reciprocal_rank: metrics::reciprocal_rank(&grades),
+        precision: metrics::precision_at(&grades, k),
+        ndcg: metrics::ndcg_at(&grades, &judgments.ideal_grades(), k),
+    }
+}
+
+fn print_report(run: &Run) {
+    println!("RR     P@{k:<4} NDCG@{k:<2} query", k = run.k);
+    for q in run.queries.iter() {
+        println!(
+            "{:.3}  {:.3}  {:.3}    {}",
+            q.reciprocal_rank, q.precision, q.ndcg, q.query
+        );
+    }
+    let (mrr, precision, ndcg) = run.means();
+    println!(
+        "\nMRR {:.3}  P@{k} {:.3}  NDCG@{k} {:.3}  ({} queries)",
+        mrr,
+        precision,
+        ndcg,
+        run.queries.len(),
+        k = run.k
+    );
+}
+
+fn print_diff(baseline: &Run, run: &Run) {
+    if baseline.k != run.k {
+        println!(
+            "\nwarning: the baseline was run with K={}, metrics are not comparable",
+            baseline.k
+        );
+    }
+    println!("\nChanges from the baseline:");
+    for q in run.queries.iter() {
+        match baseline.queries.iter().find(|b| b.query == q.query) {
+            Some(b) if b.pages != q.pages => println!(
+                "  RR {:.3} -> {:.3}  NDCG {:.3} -> {:.3}  {}\n    was {:?}\n    now {:?}",
+                b.reciprocal_rank, q.reciprocal_rank, b.ndcg, q.ndcg, q.query, b.pages, q.pages
+            ),
+            Some(_) => {}
+            None => println!("  new query: {}", q.query),
+        }
+    }
+    for b in baseline.queries.iter() {
+        if !run.queries.iter().any(|q| q.query == b.query) {
+            println!("  removed query: {}", b.query);
+        }
+    }
+    let (base_mrr, base_precision, base_ndcg) = baseline.means();
+    let (mrr, precision, ndcg) = run.means();
+    println!(
+        "MRR {:.3} -> {:.3} ({:+.3})  P@{k} {:.3} -> {:.3} ({:+.3})  NDCG@{k} {:.3} -> {:.3} ({:+.3})",
+        base_mrr,
+        mrr,
+        mrr - base_mrr,
+        base_precision,
+        precision,
+        precision - base_precision,
+        base_ndcg,
+        ndcg,
+        ndcg - base_ndcg,
+        k = run.k
+    );
+}
+
+fn main() -> io::Result<()> {
+    let index_path = env::var("INDEX_FILE").unwrap_or_else(|_| "db/search-index.bin".to_owned());
+    let judgments_path =
+        env::var_os("JUDGMENTS_FILE").unwrap_or_else(|| "judgments.json".into());
+    let k = env::var("K")
+        .ok()
+        .and_then(|k| k.parse().ok())
+        .unwrap_or(SearchOptions::default().limit);
+    let scoring = match env::var("SCORING") {
+        Ok(scoring) => Scoring::parse(&scoring)
+            .unwrap_or_else(|| panic!("unknown scoring {:?}, expected raw or bm25", scoring)),
+        Err(_) => Scoring::default(),
+    };
+
+    let search_index = MappedSearchIndex::open(&index_path)
+        .unwrap_or_else(|e| panic!("failed to load search index at {}: {}", index_path, e));
+    let judgments = judgments::load(judgments_path.as_ref())?;
+
+    let synonyms = match env::var_os("SYNONYMS_FILE") {
+        Some(path) => Arc::new(Synonyms::load(&path).unwrap_or_else(|e| {
+            panic!("failed to load synonyms at {}: {}", path.to_string_lossy(), e)
+        })),
+        None => Synonyms::builtin(),
+    };
+    // Queries normalized with other synonyms would make the metrics meaningless.
+    if let Some(mismatch) = search_index.synonyms_mismatch(&synonyms) {
+        panic!("{}", mismatch);
+    }
+    let language = match env::var("QUERY_LANGUAGE") {
+        Ok(language) => Language::parse(&language)
+            .unwrap_or_else(|| panic!("unknown language {:?}", language)),
+        Err(_) => Language::default(),
+    };
+
+    let defaults = SearchOptions::default();
+    let tile_size = env::var("TILE_SIZE")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(defaults.tile_size);
+    let hotspot_radius = env::var("HOTSPOT_RADIUS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(defaults.hotspot_radius);
+
+    let options = SearchOptions {
+        limit: k,
+        max_highlights: 0,
+        scoring,
+        synonyms,
+        expand_synonyms: search_index.has_query_synonyms(),
+        language,
+        tile_size,
+        hotspot_radius,
+        ..defaults
+    };
+    let run = Run {
+        k,
+        queries: judgments
+            .iter()
+            .map(|(query, judgments)| evaluate_query(&search_index, query, judgments, &options))
+            .collect(),
+    };
+    print_report(&run);
+
+    if let Some(baseline_path) = env::var_os("BASELINE_FILE") {
+        let baseline: Run =
+            serde_json::from_reader(BufReader::new(File::open(baseline_path)?))?;
+        print_diff(&baseline, &run);
+    }
+
+    if let Some(run_path) = env::var_os("RUN_FILE") {
+        serde_json::to_writer_pretty(BufWriter::new(File::create(run_path)?), &run)?;
+    }
+
+    Ok(())
+}
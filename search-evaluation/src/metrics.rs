@@ -0,0 +1,59 @@
+//! Ranking quality metrics, computed from the grades of the returned pages in ranking order.
+
+/// Returns the inverse of the rank of the first relevant page, or 0 if there is none.
+pub(crate) fn reciprocal_rank(grades: &[u32]) -> f32 {
+    grades
+        .iter()
+        .position(|g| *g > 0)
+        .map(|i| 1. / (i + 1) as f32)
+        .unwrap_or(0.)
+}
+
+/// Returns the proportion of relevant pages among the first `k` ones. Missing pages count as not
+/// relevant.
+pub(crate) fn precision_at(grades: &[u32], k: usize) -> f32 {
+    if k == 0 {
+        return 0.;
+    }
+    grades.iter().take(k).filter(|g| **g > 0).count() as f32 / k as f32
+}
+
+fn discounted_cumulative_gain(grades: &[u32], k: usize) -> f32 {
+    grades
+        .iter()
+        .take(k)
+        .enumerate()
+        .map(|(i, g)| (2f32.powi(*g as i32) - 1.) / ((i + 2) as f32).log2())
+        .sum()
+}
+
+/// Returns the discounted cumulative gain of the first `k` pages, normalized by the one of the
+/// best possible ranking, given by `ideal_grades`.
+pub(crate) fn ndcg_at(grades: &[u32], ideal_grades: &[u32], k: usize) -> f32 {
+    let ideal = discounted_cumulative_gain(ideal_grades, k);
+    if ideal == 0. {
+        return 0.;
+    }
+    discounted_cumulative_gain(grades, k) / ideal
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ndcg_at, precision_at, reciprocal_rank};
+
+    #[test]
+    fn metrics() {
+        assert_eq!(reciprocal_rank(&[0, 0, 2]), 1. / 3.);
+        assert_eq!(reciprocal_rank(&[0, 0]), 0.);
+
+        assert_eq!(precision_at(&[1, 0, 3], 5), 0.4);
+        assert_eq!(precision_at(&[1, 0, 3], 2), 0.5);
+
+        assert_eq!(ndcg_at(&[3, 1], &[3, 1], 5), 1.);
+        assert_eq!(ndcg_at(&[0, 0], &[3], 5), 0.);
+        // The ideal ranking puts the page of grade 3 first.
+        let ndcg = ndcg_at(&[1, 3], &[3, 1], 5);
+        let expected = (1. + 7. / 3f32.log2()) / (7. + 1. / 3f32.log2());
+        assert!((ndcg - expected).abs() < 1e-6);
+    }
+}
@@ -1,9 +1,51 @@
+use std::{
+    fs::File,
+    io::{self, BufReader},
+    path::Path,
+};
+
 use serde::Deserialize;
+use serde_json::{Map, Value};
+
+/// Name of the configuration file, in the lessons directory, whose fields apply to all the
+/// lessons that don't set them.
+pub(crate) const DEFAULT_CONFIG_FILE_NAME: &str = "default.json";
+
+/// Field of the default configuration that maps subjects to the fields that apply to their
+/// lessons.
+const SUBJECTS_FIELD: &str = "subjects";
 
 fn one() -> f32 {
     1.
 }
 
+fn average_font_size() -> f32 {
+    10.
+}
+
+fn font_size_std() -> f32 {
+    2.
+}
+
+/// Lines that start with `word` get at least `importance`, between 0 and 1, which is checked
+/// when loading the configuration.
+#[derive(Clone, Deserialize)]
+pub(crate) struct Boost {
+    /// Compared after normalization, so "Théorème" also matches "théorèmes".
+    pub word: String,
+    pub importance: f32,
+}
+
+fn default_boosts() -> Vec<Boost> {
+    ["Théorème", "Définition", "Propriété", "Méthode"]
+        .into_iter()
+        .map(|word| Boost {
+            word: word.to_owned(),
+            importance: 0.95,
+        })
+        .collect()
+}
+
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub(crate) struct LessonConfig {
@@ -12,10 +54,134 @@ pub(crate) struct LessonConfig {
 
     #[serde(default)]
     pub ignore_colors: Vec<Vec<u8>>,
+
+    /// Subject of the lesson, used by `subject:` filters.
+    #[serde(default)]
+    pub subject: String,
+
+    /// Chapter number of the lesson, used by `chapitre:` filters. Defaults to the number that
+    /// starts the file name, if any.
+    #[serde(default)]
+    pub chapter: Option<u16>,
+
+    /// Words that mark important lines when they start them, such as "Théorème". Replaces the
+    /// default list instead of extending it.
+    #[serde(default = "default_boosts")]
+    pub boosts: Vec<Boost>,
+
+    /// Font size of the body text, once scaled. Lines in a bigger font are more important.
+    #[serde(default = "average_font_size")]
+    pub average_font_size: f32,
+
+    /// How fast the importance of a line grows with its font size.
+    #[serde(default = "font_size_std")]
+    pub font_size_std: f32,
+
+    /// Language of the lesson, such as "fr" or "en", which selects how its words are stemmed and
+    /// which ones are ignored. French by default.
+    #[serde(default)]
+    pub language: Option<String>,
+}
+
+/// Reads a configuration file as a JSON object, which is empty if the file doesn't exist.
+pub(crate) fn read_config_fields(path: &Path) -> io::Result<Map<String, Value>> {
+    match File::open(path) {
+        Ok(f) => Ok(serde_json::from_reader(BufReader::new(f))?),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Map::new()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Builds the configuration of a lesson from its own fields, completed by the fields of the
+/// default configuration for its subject, of the default configuration and then by the built-in
+/// defaults. Fields are replaced as a whole, e.g. the boosts of a lesson replace the default ones.
+pub(crate) fn merge_config(
+    defaults: &Map<String, Value>,
+    lesson: Map<String, Value>,
+) -> serde_json::Result<LessonConfig> {
+    let mut fields = defaults.clone();
+    let subjects = fields.remove(SUBJECTS_FIELD);
+    let subject = lesson.get("subject").or_else(|| fields.get("subject"));
+    if let (Some(Value::Object(subjects)), Some(Value::String(subject))) = (&subjects, subject) {
+        if let Some(Value::Object(subject_fields)) = subjects.get(subject) {
+            fields.extend(subject_fields.clone());
+        }
+    }
+    fields.extend(lesson);
+    let config: LessonConfig = serde_json::from_value(Value::Object(fields))?;
+    if let Some(b) = config
+        .boosts
+        .iter()
+        .find(|b| !(0. ..=1.).contains(&b.importance))
+    {
+        return Err(serde::de::Error::custom(format!(
+            "the importance of the boost of {:?} is {}, not between 0 and 1",
+            b.word, b.importance
+        )));
+    }
+    Ok(config)
 }
 
-impl Default for LessonConfig {
-    fn default() -> Self {
-        Self { scale: 1., ignore_colors: Vec::new() }
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::merge_config;
+
+    fn object(value: Value) -> serde_json::Map<String, Value> {
+        match value {
+            Value::Object(fields) => fields,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn merging() {
+        let defaults = object(json!({
+            "averageFontSize": 12,
+            "boosts": [{"word": "Loi", "importance": 0.9}],
+            "subject": "physique",
+            "language": "en"
+        }));
+
+        let config = merge_config(&defaults, object(json!({"subject": "maths"}))).unwrap();
+        assert_eq!(config.subject, "maths");
+        assert_eq!(config.average_font_size, 12.);
+        assert_eq!(config.font_size_std, 2.);
+        assert_eq!(config.boosts.len(), 1);
+        assert_eq!(config.boosts[0].word, "Loi");
+        assert_eq!(config.language.as_deref(), Some("en"));
+
+        let config = merge_config(&Default::default(), Default::default()).unwrap();
+        assert_eq!(config.scale, 1.);
+        assert_eq!(config.boosts.len(), 4);
+    }
+
+    #[test]
+    fn subject_defaults() {
+        let defaults = object(json!({
+            "scale": 2,
+            "subjects": {
+                "physique": {"boosts": [{"word": "Loi", "importance": 0.9}], "scale": 0.5}
+            }
+        }));
+
+        let config = merge_config(&defaults, object(json!({"subject": "physique"}))).unwrap();
+        assert_eq!(config.scale, 0.5);
+        assert_eq!(config.boosts[0].word, "Loi");
+        let lesson = object(json!({"subject": "physique", "scale": 1}));
+        assert_eq!(merge_config(&defaults, lesson).unwrap().scale, 1.);
+        let config = merge_config(&defaults, object(json!({"subject": "maths"}))).unwrap();
+        assert_eq!(config.scale, 2.);
+        assert_eq!(config.boosts.len(), 4);
+    }
+
+    #[test]
+    fn boost_importance() {
+        for importance in [1.5, -0.1] {
+            let boosts = json!([{"word": "Loi", "importance": importance}]);
+            let lesson = object(json!({ "boosts": boosts }));
+            assert!(merge_config(&Default::default(), lesson).is_err());
+        }
     }
 }
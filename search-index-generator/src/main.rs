@@ -1,20 +1,46 @@
 use std::{
     env,
     fs::{self, File, OpenOptions},
-    io::{self, BufReader},
+    io,
     path::{Path, PathBuf},
-    sync::{Mutex, RwLock}, ffi::{OsStr, OsString},
+    sync::{Arc, Mutex, RwLock}, ffi::{OsStr, OsString},
+    time::{SystemTime, UNIX_EPOCH},
 };
 
-use mupdf::{pdf::PdfDocument, Colorspace, Matrix, Outline, TextPageOptions};
+use mupdf::{pdf::PdfDocument, Colorspace, Matrix, Outline, TextLine, TextPageOptions};
 use rayon::prelude::*;
-use search_index::index::{Match, Page, SearchIndex, SearchResult};
+use search_index::{
+    index::{
+        Document, Match, OutlineEntry, Page, SearchIndex, SearchResult, WordBox, WordPosition,
+    },
+    normalize::{normalize, tokenize, Language},
+    synonyms::{Synonyms, QUERY_SYNONYMS, SYNONYMS_SETTING},
+};
 
-use crate::config::LessonConfig;
+use crate::config::{merge_config, read_config_fields, LessonConfig, DEFAULT_CONFIG_FILE_NAME};
 
 mod page_render_cache;
 mod config;
 
+/// Scale at which pages are rendered, before applying the per-lesson scale.
+const DEFAULT_SCALE: f32 = 1.8;
+
+/// Settings of the generator that apply to all the lessons.
+pub(crate) struct BuildOptions {
+    /// Whether to record the horizontal extent of each word, to highlight words tightly. This
+    /// makes the index bigger.
+    pub word_boxes: bool,
+    /// The synonyms to canonicalize, which the search must also use, or `None` to let the search
+    /// expand them.
+    pub synonyms: Option<Arc<Synonyms>>,
+}
+
+impl BuildOptions {
+    fn index_synonyms(&self) -> &Synonyms {
+        self.synonyms.as_deref().unwrap_or(Synonyms::none())
+    }
+}
+
 fn find_first_useful_outline(outlines: &[Outline]) -> Option<&Outline> {
     let o = outlines
         .iter()
@@ -25,21 +51,85 @@ fn find_first_useful_outline(outlines: &[Outline]) -> Option<&Outline> {
     find_first_useful_outline(&o.down)
 }
 
+/// Adds outline entries and their descendants to `search_index.outline`, parents first, and
+/// returns the page and the height where each entry that has a page starts, with its index.
+fn flatten_outline(
+    outlines: &[Outline],
+    parent: Option<u32>,
+    depth: u16,
+    search_index: &mut SearchIndex,
+) -> Vec<(u32, f32, u32)> {
+    let mut positions = Vec::new();
+    for o in outlines {
+        let index = search_index.outline.len() as u32;
+        search_index.outline.push(OutlineEntry {
+            document_index: 0,
+            parent,
+            depth,
+            title: o.title.clone(),
+        });
+        if let Some(page) = o.page {
+            positions.push((page, o.y, index));
+        }
+        positions.extend(flatten_outline(&o.down, Some(index), depth + 1, search_index));
+    }
+    positions
+}
+
+/// Builds the text of a line, with the byte offset in the text and the horizontal extent of each
+/// of its characters.
+fn line_text(line: &TextLine) -> (String, Vec<(usize, f32, f32)>) {
+    let mut text = String::new();
+    let mut extents = Vec::new();
+    for c in line.chars() {
+        if let Some(ch) = c.char() {
+            let quad = c.quad();
+            let x0 = quad.ul.x.min(quad.ll.x);
+            let x1 = quad.ur.x.max(quad.lr.x);
+            extents.push((text.len(), x0, x1));
+            text.push(ch);
+        }
+    }
+    (text, extents)
+}
+
 fn build_search_index_from_document(
     document_path: &Path,
     rendered_pages_path: &Path,
     cache: &RwLock<page_render_cache::DocumentMap>,
+    default_config: &serde_json::Map<String, serde_json::Value>,
+    options: &BuildOptions,
 ) -> SearchIndex {
     eprintln!("Processing {}...", document_path.display());
 
     let mut config_ext = document_path.extension().map(|s| s.to_owned()).unwrap_or_else(|| OsString::new());
     config_ext.push(".json");
     let config_path = document_path.with_extension(config_ext);
-    let config: LessonConfig = match File::open(&config_path) {
-        Ok(f) => serde_json::from_reader(BufReader::new(f)).unwrap(),
-        Err(e) if e.kind() == io::ErrorKind::NotFound => Default::default(),
-        Err(e) => panic!("failed to open lesson config at {}: {}", config_path.display(), e),
+    let config: LessonConfig = read_config_fields(&config_path)
+        .and_then(|fields| Ok(merge_config(default_config, fields)?))
+        .unwrap_or_else(|e| {
+            panic!("failed to read lesson config at {}: {}", config_path.display(), e)
+        });
+    let language = match &config.language {
+        Some(l) => Language::parse(l).unwrap_or_else(|| {
+            panic!("unknown language {} in {}", l, config_path.display())
+        }),
+        None => Language::default(),
     };
+    // The search only knows the language of the documents, so they are normalized with the
+    // profile of their language.
+    let normalizer = language.profile();
+    // The boosts are compared to the first normalized word of the lines.
+    let boosts: Vec<(String, f32)> = config
+        .boosts
+        .iter()
+        .filter_map(|b| {
+            let word = normalize(&b.word, normalizer, options.index_synonyms())
+                .into_iter()
+                .next()?;
+            Some((word, b.importance))
+        })
+        .collect();
 
     let document_name = document_path.file_name().unwrap().to_str().unwrap();
 
@@ -62,11 +152,33 @@ fn build_search_index_from_document(
     let content_start = first_useful_outline.map(|o| (o.page.unwrap(), o.y));
 
     let mut search_index = SearchIndex::new();
-    search_index.documents.push(document_name.to_owned());
+    let chapter = config.chapter.or_else(|| {
+        let digits: String = document_name.chars().take_while(|c| c.is_ascii_digit()).collect();
+        digits.parse().ok()
+    });
+    search_index.documents.push(Document {
+        name: document_name.to_owned(),
+        subject: config.subject.clone(),
+        chapter,
+        language,
+    });
 
-    const DEFAULT_SCALE: f32 = 1.8;
     let scale = DEFAULT_SCALE * config.scale;
 
+    // A line belongs to the last outline entry that starts before it. The sort is stable, so
+    // children that start at the same place as their parent come after it.
+    let mut outline_positions = flatten_outline(&outlines, None, 0, &mut search_index);
+    outline_positions.sort_by(|a, b| (a.0, a.1).partial_cmp(&(b.0, b.1)).unwrap());
+    let outline_title_words: Vec<Vec<String>> = search_index
+        .outline
+        .iter()
+        .map(|e| {
+            let mut words = normalize(&e.title, normalizer, options.index_synonyms());
+            words.sort();
+            words
+        })
+        .collect();
+
     for (page_nr, page) in doc.pages().unwrap().enumerate() {
         let page = page.unwrap();
 
@@ -100,7 +212,7 @@ fn build_search_index_from_document(
                     }
                 }
 
-                let line: String = l.chars().flat_map(|c| c.char()).collect();
+                let (line, extents) = line_text(&l);
 
                 let check_color = |x, y| {
                     let index = y as usize * pixmap.width() as usize * 3 + x as usize * 3;
@@ -119,7 +231,8 @@ fn build_search_index_from_document(
                     continue;
                 }
 
-                let mut words = search_index::normalize::normalize_and_extract_words(&line);
+                let tokens = tokenize(&line, normalizer, options.index_synonyms());
+                let mut words: Vec<String> = tokens.iter().map(|t| t.word.clone()).collect();
                 if words.is_empty() {
                     continue;
                 }
@@ -130,17 +243,28 @@ fn build_search_index_from_document(
                     / (line.len() as f32))
                     .sqrt();
                 font_size *= config.scale;
-                const AVG_FONT_SIZE: f32 = 10.;
-                const FONT_SIZE_STD: f32 = 2.;
-                let mut importance = 1. / (1. + (-(font_size - AVG_FONT_SIZE) / FONT_SIZE_STD).clamp(-10., 10.).exp());
-                // Boost certain patterns. Note that the words are stemmed.
-                if words.len() >= 2
-                    && (words[0] == "theorem"
-                        || words[0] == "definit"
-                        || words[0] == "propriet"
-                        || words[0] == "method")
-                {
-                    importance = importance.max(0.95);
+                let deviation = (font_size - config.average_font_size) / config.font_size_std;
+                let mut importance = 1. / (1. + (-deviation).clamp(-10., 10.).exp());
+                // Boost lines that start with certain words, such as "Théorème".
+                if words.len() >= 2 {
+                    for (word, boost) in boosts.iter() {
+                        if words[0] == *word {
+                            importance = importance.max(*boost);
+                        }
+                    }
+                }
+                let preceding_entries = outline_positions
+                    .partition_point(|(p, y, _)| (*p as usize, *y) <= (page_nr, bounds.y1));
+                let outline_position =
+                    preceding_entries.checked_sub(1).map(|i| outline_positions[i]);
+                // Boost the titles of the outline entries, on the page where they start.
+                if let Some((entry_page, _, entry)) = outline_position {
+                    let title_words = &outline_title_words[entry as usize];
+                    if entry_page as usize == page_nr
+                        && words.iter().all(|w| title_words.binary_search(w).is_ok())
+                    {
+                        importance = 1.;
+                    }
                 }
                 score += importance;
 
@@ -156,6 +280,38 @@ fn build_search_index_from_document(
                     width: ((bounds.x1 - bounds.x0) * scale) as u16,
                     height: ((bounds.y1 - bounds.y0) * scale) as u16,
                 });
+                search_index
+                    .result_outline
+                    .push(outline_position.map(|(_, _, entry)| entry));
+
+                // The words of the canonical form of a synonym all get the box of the whole
+                // synonym.
+                if options.word_boxes {
+                    for token in tokens.iter() {
+                        let (x0, x1) = extents
+                            .iter()
+                            .filter(|(offset, _, _)| token.offsets.contains(offset))
+                            .fold((f32::INFINITY, f32::NEG_INFINITY), |(x0, x1), e| {
+                                (x0.min(e.1), x1.max(e.2))
+                            });
+                        if x0 <= x1 {
+                            search_index.word_boxes.push(WordBox {
+                                result_index: result_index as u32,
+                                word: token.word.clone(),
+                                x: (x0 * scale) as i16,
+                                width: ((x1 - x0) * scale) as u16,
+                            });
+                        }
+                    }
+                }
+                for token in tokens.iter() {
+                    search_index.word_positions.push(WordPosition {
+                        result_index: result_index as u32,
+                        word: token.word.clone(),
+                        position: token.position as u16,
+                    });
+                }
+                search_index.result_texts.push(line);
 
                 for w in words.iter() {
                     search_index
@@ -289,12 +445,19 @@ fn build_search_index_from_document(
 
     if search_index.pages.is_empty() {
         search_index.documents.clear();
+        search_index.outline.clear();
     }
 
     search_index
 }
 
-pub(crate) fn build_search_index(lessons_dir: &Path, out_dir: &Path) -> io::Result<()> {
+/// Builds the search index of the lessons in `lessons_dir` and renders their pages into
+/// `out_dir`.
+pub(crate) fn build_search_index(
+    lessons_dir: &Path,
+    out_dir: &Path,
+    options: &BuildOptions,
+) -> io::Result<()> {
     fs::create_dir_all(&out_dir)?;
 
     let cache = match File::open(out_dir.join("document-render-cache.bin")) {
@@ -311,6 +474,11 @@ pub(crate) fn build_search_index(lessons_dir: &Path, out_dir: &Path) -> io::Resu
         }
     }
 
+    let default_config_path = lessons_dir.join(DEFAULT_CONFIG_FILE_NAME);
+    let default_config = read_config_fields(&default_config_path).unwrap_or_else(|e| {
+        panic!("failed to read default config at {}: {}", default_config_path.display(), e)
+    });
+
     let search_index = Mutex::new(SearchIndex::new());
     fs::read_dir(lessons_dir)
         .unwrap()
@@ -320,7 +488,15 @@ pub(crate) fn build_search_index(lessons_dir: &Path, out_dir: &Path) -> io::Resu
             e.metadata().unwrap().is_file()
                 && e.path().extension().map(|e| e == "pdf").unwrap_or(false)
         })
-        .map(|e| build_search_index_from_document(&e.path(), &rendered_pages_path, &cache))
+        .map(|e| {
+            build_search_index_from_document(
+                &e.path(),
+                &rendered_pages_path,
+                &cache,
+                &default_config,
+                options,
+            )
+        })
         .for_each(|mut partial_index| {
             // Merge partial index into global index.
             let mut i = search_index.lock().unwrap();
@@ -339,6 +515,20 @@ pub(crate) fn build_search_index(lessons_dir: &Path, out_dir: &Path) -> io::Resu
             }
             let result_index_base = i.results.len() as u32;
             i.results.extend_from_slice(&partial_index.results);
+            i.result_texts.extend_from_slice(&partial_index.result_texts);
+
+            let outline_index_base = i.outline.len() as u32;
+            for e in partial_index.outline.iter_mut() {
+                e.document_index += document_index_base;
+                if let Some(parent) = e.parent.as_mut() {
+                    *parent += outline_index_base;
+                }
+            }
+            i.outline.extend_from_slice(&partial_index.outline);
+            for entry in partial_index.result_outline.iter_mut().flatten() {
+                *entry += outline_index_base;
+            }
+            i.result_outline.extend_from_slice(&partial_index.result_outline);
 
             for (word, mut partial_matches) in partial_index.words.into_iter() {
                 let matches = i.words.entry(word).or_default();
@@ -347,17 +537,54 @@ pub(crate) fn build_search_index(lessons_dir: &Path, out_dir: &Path) -> io::Resu
                 }
                 matches.extend_from_slice(&partial_matches);
             }
+
+            for b in partial_index.word_boxes.iter_mut() {
+                b.result_index += result_index_base;
+            }
+            i.word_boxes.extend_from_slice(&partial_index.word_boxes);
+
+            for p in partial_index.word_positions.iter_mut() {
+                p.result_index += result_index_base;
+            }
+            i.word_positions.extend_from_slice(&partial_index.word_positions);
         });
 
+    {
+        let mut i = search_index.lock().unwrap();
+        i.metadata.build_timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let settings = &mut i.metadata.generator_settings;
+        settings.insert(
+            "generator".to_owned(),
+            concat!(env!("CARGO_PKG_NAME"), " ", env!("CARGO_PKG_VERSION")).to_owned(),
+        );
+        settings.insert("default_scale".to_owned(), DEFAULT_SCALE.to_string());
+        settings.insert("word_boxes".to_owned(), options.word_boxes.to_string());
+        let synonyms = match &options.synonyms {
+            Some(synonyms) => synonyms.fingerprint(),
+            None => QUERY_SYNONYMS.to_owned(),
+        };
+        settings.insert(SYNONYMS_SETTING.to_owned(), synonyms);
+
+        eprintln!("Postings size: {}", i.postings_size_report());
+    }
+
+    // Write to a temporary file and rename it, because search servers may have the index memory
+    // mapped and modifying it in place would change it under their feet.
+    let search_index_tmp_path = out_dir.join("search-index.bin.tmp");
     let mut search_index_file = OpenOptions::new()
         .create(true)
         .truncate(true)
         .write(true)
-        .open(out_dir.join("search-index.bin"))?;
+        .open(&search_index_tmp_path)?;
     search_index
         .lock()
         .unwrap()
         .serialize(&mut search_index_file)?;
+    drop(search_index_file);
+    fs::rename(&search_index_tmp_path, out_dir.join("search-index.bin"))?;
 
     let mut cache_file = OpenOptions::new()
         .create(true)
@@ -374,21 +601,40 @@ fn main() -> io::Result<()> {
         .unwrap_or_else(|| "lessons".into())
         .into();
     let out_dir: PathBuf = env::var_os("OUT_DIR").unwrap_or_else(|| "db".into()).into();
-    build_search_index(&lessons_dir, &out_dir)
+    // Word boxes make the index bigger, so they are opt-in.
+    let word_boxes = env::var_os("WORD_BOXES").map(|v| v == "1").unwrap_or(false);
+    // With query-time synonyms, the synonyms can change without rebuilding the index.
+    let synonyms = if env::var_os("QUERY_SYNONYMS").map(|v| v == "1").unwrap_or(false) {
+        None
+    } else {
+        Some(match env::var_os("SYNONYMS_FILE") {
+            Some(path) => Arc::new(Synonyms::load(path)?),
+            None => Synonyms::builtin(),
+        })
+    };
+    let options = BuildOptions {
+        word_boxes,
+        synonyms,
+    };
+    build_search_index(&lessons_dir, &out_dir, &options)
 }
 
 #[cfg(test)]
 mod tests {
-    use std::{path::Path, fs::File};
+    use std::path::Path;
 
-    use search_index::{index::SearchIndex, search::search};
+    use search_index::{mapped::MappedSearchIndex, search::search, synonyms::Synonyms};
 
-    use super::build_search_index;
+    use super::{build_search_index, BuildOptions};
 
     #[test]
     fn good_results() {
-        build_search_index(Path::new("../lessons"), Path::new("../db-test")).unwrap();
-        let search_index = SearchIndex::deserialize(&mut File::open("../db-test/search-index.bin").unwrap()).unwrap();
+        let options = BuildOptions {
+            word_boxes: true,
+            synonyms: Some(Synonyms::builtin()),
+        };
+        build_search_index(Path::new("../lessons"), Path::new("../db-test"), &options).unwrap();
+        let search_index = MappedSearchIndex::open("../db-test/search-index.bin").unwrap();
 
         // Excerpts from colle #19
 
@@ -0,0 +1,55 @@
+//! Edit distances used to match query words that contain typos.
+
+/// Factor applied to the score of a match for each edit needed to go from the query word to the
+/// indexed word.
+pub const EDIT_PENALTY: f32 = 0.5;
+
+/// Returns how many edits are allowed between a query word and an indexed word. Short words get
+/// no budget because a single edit is enough to turn them into many unrelated words.
+pub fn max_edit_distance(word: &str) -> u32 {
+    match word.chars().count() {
+        0..=3 => 0,
+        4..=6 => 1,
+        _ => 2,
+    }
+}
+
+/// Returns the distance between `a` and each prefix of `b`, by length.
+fn last_row(a: &str, b: &str) -> Vec<u32> {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<u32> = (0..=(b.len() as u32)).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut previous_diagonal = row[0];
+        row[0] = i as u32 + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous_diagonal + if ca == *cb { 0 } else { 1 };
+            previous_diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    row
+}
+
+/// Returns the minimum number of insertions, deletions and substitutions to turn `a` into `b`.
+pub fn edit_distance(a: &str, b: &str) -> u32 {
+    *last_row(a, b).last().unwrap()
+}
+
+/// Returns the minimum number of edits to turn `a` into a prefix of `b`.
+pub fn prefix_edit_distance(a: &str, b: &str) -> u32 {
+    last_row(a, b).into_iter().min().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{edit_distance, prefix_edit_distance};
+
+    #[test]
+    fn distances() {
+        assert_eq!(edit_distance("tchebichev", "tchebychev"), 1);
+        assert_eq!(edit_distance("schwartz", "schwarz"), 1);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(prefix_edit_distance("tchebich", "tchebychev"), 1);
+        assert_eq!(prefix_edit_distance("orthog", "orthogonal"), 0);
+    }
+}
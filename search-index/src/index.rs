@@ -1,14 +1,28 @@
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, BTreeSet},
+    error::Error,
+    fmt,
     io::{self, Read, Write},
 };
 
+use crate::{
+    mapped::pack_postings_position,
+    normalize::Language,
+    postings::{encode_postings, PostingsSizeReport},
+};
+
 fn deserialize_u32<R: Read>(r: &mut R) -> io::Result<u32> {
     let mut buf = [0u8; 4];
     r.read_exact(&mut buf)?;
     Ok(u32::from_le_bytes(buf))
 }
 
+fn deserialize_u64<R: Read>(r: &mut R) -> io::Result<u64> {
+    let mut buf = [0u8; 8];
+    r.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
 fn deserialize_f32<R: Read>(r: &mut R) -> io::Result<f32> {
     let mut buf = [0u8; 4];
     r.read_exact(&mut buf)?;
@@ -47,6 +61,17 @@ fn deserialize_vec_string<R: Read>(r: &mut R) -> io::Result<Vec<String>> {
     Ok(vec)
 }
 
+/// A lesson and the metadata declared in its configuration.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Document {
+    pub name: String,
+    /// E.g. "maths" or "physique", empty if unknown.
+    pub subject: String,
+    pub chapter: Option<u16>,
+    /// The language that the words of the document were normalized for.
+    pub language: Language,
+}
+
 #[derive(Clone)]
 pub struct Page {
     pub document_index: u16,
@@ -104,6 +129,39 @@ impl SearchResult {
     }
 }
 
+/// An entry of the outline of a document, such as a chapter, a section or a subsection.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OutlineEntry {
+    pub document_index: u16,
+    /// The enclosing entry, as an index in `SearchIndex::outline`.
+    pub parent: Option<u32>,
+    /// 0 for the top-level entries.
+    pub depth: u16,
+    pub title: String,
+}
+
+/// The horizontal extent of an occurrence of a word in a line, to highlight the word instead of
+/// the whole line. Words share the vertical extent of their line.
+#[derive(Clone)]
+pub struct WordBox {
+    pub result_index: u32,
+    /// The normalized word, as in `SearchIndex::words`.
+    pub word: String,
+    pub x: i16,
+    pub width: u16,
+}
+
+/// The position of an occurrence of a word among the normalized words of its line, to check
+/// that the words of a phrase follow each other.
+#[derive(Clone)]
+pub struct WordPosition {
+    pub result_index: u32,
+    /// The normalized word, as in `SearchIndex::words`.
+    pub word: String,
+    /// As in `normalize::Token::position`.
+    pub position: u16,
+}
+
 #[derive(Clone)]
 pub struct Match {
     pub result_index: u32,
@@ -122,12 +180,202 @@ impl Match {
     }
 }
 
+/// Bytes at the start of every search index file.
+pub const MAGIC: [u8; 8] = *b"LSINDEX\0";
+
+/// Version of the file layout written by `SearchIndex::serialize`.
+pub const FORMAT_VERSION: u32 = 12;
+
+pub(crate) const SECTION_DOCUMENTS: u32 = 1;
+pub(crate) const SECTION_PAGES: u32 = 2;
+pub(crate) const SECTION_RESULTS: u32 = 3;
+pub(crate) const SECTION_WORDS: u32 = 4;
+pub(crate) const SECTION_POSTINGS: u32 = 5;
+pub(crate) const SECTION_STRINGS: u32 = 6;
+pub(crate) const SECTION_STATISTICS: u32 = 7;
+pub(crate) const SECTION_WORD_BOXES: u32 = 8;
+pub(crate) const SECTION_TEXT_BLOCKS: u32 = 9;
+pub(crate) const SECTION_TEXT: u32 = 10;
+pub(crate) const SECTION_OUTLINE: u32 = 11;
+pub(crate) const SECTION_WORD_POSITIONS: u32 = 12;
+
+// Sizes of the fixed-size records that make up the sections, so that they can be indexed in
+// place. Strings are stored as an offset and a length in the strings section. The words section
+// is a finite-state transducer instead and the postings section is described in the `postings`
+// module. The statistics section is a single record. Word boxes refer to their word by its
+// `word_hash` and are sorted by result and hash, so that the boxes of a word in a line can be
+// found with a binary search, and so are word positions. The text of the lines is compressed in
+// blocks of `LINES_PER_TEXT_BLOCK` consecutive results, whose offset and length in the text
+// section are stored in the text blocks section.
+pub(crate) const DOCUMENT_RECORD_SIZE: usize = 20;
+pub(crate) const PAGE_RECORD_SIZE: usize = 28;
+pub(crate) const RESULT_RECORD_SIZE: usize = 16;
+pub(crate) const STATISTICS_RECORD_SIZE: usize = 4;
+pub(crate) const WORD_BOX_RECORD_SIZE: usize = 12;
+pub(crate) const TEXT_BLOCK_RECORD_SIZE: usize = 8;
+pub(crate) const OUTLINE_RECORD_SIZE: usize = 16;
+pub(crate) const WORD_POSITION_RECORD_SIZE: usize = 10;
+
+/// Lines are compressed together because a single line is too short to compress well, and a
+/// block is small enough to be decompressed for each returned line.
+pub(crate) const LINES_PER_TEXT_BLOCK: usize = 64;
+
+/// Stored in place of the chapter of documents that don't have one.
+pub(crate) const NO_CHAPTER: u16 = u16::MAX;
+
+/// Stored in place of the outline entry of results and of the parent of outline entries that
+/// don't have one.
+pub(crate) const NO_OUTLINE_ENTRY: u32 = u32::MAX;
+
+/// Identifies a word in the word boxes section. Collisions only matter between the words of a
+/// single line, where they are very unlikely.
+pub(crate) fn word_hash(word: &str) -> u32 {
+    crc32fast::hash(word.as_bytes())
+}
+
+/// An entry of the section table: the ID of the section, its offset from the start of the file
+/// and its length.
+pub(crate) type SectionEntry = (u32, u64, u64);
+
+/// An error that happened while loading a search index file.
+#[derive(Debug)]
+pub enum IndexError {
+    Io(io::Error),
+    /// The file does not start with `MAGIC`. It is either not a search index or was written
+    /// before the header was introduced, in which case `SearchIndex::deserialize_headerless` can
+    /// read it.
+    WrongMagic,
+    /// The file was written by a different version of the generator and must be regenerated.
+    UnsupportedVersion(u32),
+    /// The file is truncated or its content does not match the checksum.
+    Corrupted(&'static str),
+}
+
+impl fmt::Display for IndexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IndexError::Io(e) => write!(f, "I/O error: {}", e),
+            IndexError::WrongMagic => write!(f, "not a search index file (wrong magic bytes)"),
+            IndexError::UnsupportedVersion(v) => write!(
+                f,
+                "unsupported format version {} (expected {})",
+                v, FORMAT_VERSION
+            ),
+            IndexError::Corrupted(reason) => write!(f, "corrupted search index: {}", reason),
+        }
+    }
+}
+
+impl Error for IndexError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            IndexError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for IndexError {
+    fn from(e: io::Error) -> Self {
+        IndexError::Io(e)
+    }
+}
+
+/// Information about how a search index was built, stored in the file header.
+#[derive(Clone, Default)]
+pub struct IndexMetadata {
+    /// Seconds since the Unix epoch at which the index was generated.
+    pub build_timestamp: u64,
+    /// Settings that the generator was run with, e.g. the render scale.
+    pub generator_settings: BTreeMap<String, String>,
+}
+
+/// Checks the magic bytes, the version and the checksum of a search index file, and reads the
+/// metadata and the section table from its header.
+pub(crate) fn parse_header(bytes: &[u8]) -> Result<(IndexMetadata, Vec<SectionEntry>), IndexError> {
+    if bytes.len() < MAGIC.len() || bytes[..MAGIC.len()] != MAGIC {
+        return Err(IndexError::WrongMagic);
+    }
+    let mut header = &bytes[MAGIC.len()..];
+    let version =
+        deserialize_u32(&mut header).map_err(|_| IndexError::Corrupted("truncated header"))?;
+    if version != FORMAT_VERSION {
+        return Err(IndexError::UnsupportedVersion(version));
+    }
+
+    if bytes.len() < MAGIC.len() + 4 + 4 {
+        return Err(IndexError::Corrupted("truncated header"));
+    }
+    let (content, checksum) = bytes.split_at(bytes.len() - 4);
+    if crc32fast::hash(content).to_le_bytes() != checksum {
+        return Err(IndexError::Corrupted("checksum mismatch"));
+    }
+
+    let read_header = |r: &mut &[u8]| -> io::Result<_> {
+        let build_timestamp = deserialize_u64(r)?;
+
+        let setting_count = deserialize_u32(r)?;
+        let mut generator_settings = BTreeMap::new();
+        for _ in 0..setting_count {
+            let key = deserialize_string(r)?;
+            let value = deserialize_string(r)?;
+            generator_settings.insert(key, value);
+        }
+
+        let section_count = deserialize_u32(r)?;
+        let mut sections = Vec::with_capacity(section_count as usize);
+        for _ in 0..section_count {
+            let id = deserialize_u32(r)?;
+            let offset = deserialize_u64(r)?;
+            let len = deserialize_u64(r)?;
+            if offset.checked_add(len).map(|end| end > content.len() as u64).unwrap_or(true) {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "section out of bounds"));
+            }
+            sections.push((id, offset, len));
+        }
+
+        Ok((
+            IndexMetadata {
+                build_timestamp,
+                generator_settings,
+            },
+            sections,
+        ))
+    };
+    read_header(&mut header).map_err(|_| IndexError::Corrupted("invalid header"))
+}
+
+#[derive(Default)]
+struct StringPool(Vec<u8>);
+
+impl StringPool {
+    fn write<W: Write>(&mut self, w: &mut W, s: &str) -> io::Result<()> {
+        w.write_all(&(self.0.len() as u32).to_le_bytes())?;
+        w.write_all(&(s.len() as u32).to_le_bytes())?;
+        self.0.extend_from_slice(s.as_bytes());
+        Ok(())
+    }
+}
+
+/// A search index as built by the generator. Use `mapped::MappedSearchIndex` to query it once
+/// serialized.
 #[derive(Clone, Default)]
 pub struct SearchIndex {
-    pub documents: Vec<String>,
+    pub metadata: IndexMetadata,
+    pub documents: Vec<Document>,
     pub pages: Vec<Page>,
     pub results: Vec<SearchResult>,
     pub words: BTreeMap<String, Vec<Match>>,
+    /// Boxes of the words of the lines, only recorded if the generator is asked to.
+    pub word_boxes: Vec<WordBox>,
+    pub word_positions: Vec<WordPosition>,
+    /// The original text of each result, or nothing if the text is not stored.
+    pub result_texts: Vec<String>,
+    /// The outline entries of all the documents, parents first.
+    pub outline: Vec<OutlineEntry>,
+    /// The innermost outline entry that contains each result, as an index in `outline`. Missing
+    /// values count as `None`.
+    pub result_outline: Vec<Option<u32>>,
 }
 
 impl SearchIndex {
@@ -135,8 +383,16 @@ impl SearchIndex {
         Default::default()
     }
 
-    pub fn deserialize<R: Read>(r: &mut R) -> io::Result<Self> {
-        let documents = deserialize_vec_string(r)?;
+    /// Reads a search index written before the header was introduced. There is no way to detect
+    /// truncation or corruption with this layout.
+    pub fn deserialize_headerless<R: Read>(r: &mut R) -> io::Result<Self> {
+        let documents = deserialize_vec_string(r)?
+            .into_iter()
+            .map(|name| Document {
+                name,
+                ..Default::default()
+            })
+            .collect();
 
         let page_count = deserialize_u32(r)?;
         let mut pages = Vec::with_capacity(page_count as usize);
@@ -165,52 +421,299 @@ impl SearchIndex {
         }
 
         Ok(Self {
+            metadata: Default::default(),
             documents,
             pages,
             results,
             words,
+            word_boxes: Vec::new(),
+            word_positions: Vec::new(),
+            result_texts: Vec::new(),
+            outline: Vec::new(),
+            result_outline: Vec::new(),
         })
     }
 
+    /// Returns the number of matches on each page.
+    fn page_lengths(&self) -> Vec<u32> {
+        let mut lengths = vec![0; self.pages.len()];
+        for m in self.words.values().flatten() {
+            let page_index = self.results[m.result_index as usize].page_index;
+            lengths[page_index as usize] += 1;
+        }
+        lengths
+    }
+
+    /// Returns the number of pages that contain a line with one of the matches.
+    fn page_frequency(&self, matches: &[Match]) -> u32 {
+        let pages: BTreeSet<u32> = matches
+            .iter()
+            .map(|m| self.results[m.result_index as usize].page_index)
+            .collect();
+        pages.len() as u32
+    }
+
+    /// Writes the search index, preceded by a header and followed by a checksum of everything
+    /// before it. The sections are made of fixed-size records so that the file can be memory
+    /// mapped and queried without deserializing it.
+    ///
+    /// Statistics about the words and the pages are computed here for the ranking of the
+    /// results, so that they always match the content of the index.
     pub fn serialize<W: Write>(&self, w: &mut W) -> io::Result<()> {
-        w.write_all(&(self.documents.len() as u32).to_le_bytes())?;
+        let mut strings = StringPool::default();
+        let page_lengths = self.page_lengths();
+
+        let mut documents = Vec::new();
         for doc in self.documents.iter() {
-            w.write_all(&(doc.len() as u32).to_le_bytes())?;
-            w.write_all(doc.as_bytes())?;
+            strings.write(&mut documents, &doc.name)?;
+            strings.write(&mut documents, &doc.subject)?;
+            documents.write_all(&doc.chapter.unwrap_or(NO_CHAPTER).to_le_bytes())?;
+            documents.write_all(&doc.language.id().to_le_bytes())?;
+        }
+
+        let mut pages = Vec::new();
+        for (page, length) in self.pages.iter().zip(page_lengths.iter()) {
+            pages.write_all(&page.document_index.to_le_bytes())?;
+            pages.write_all(&page.page_nr.to_le_bytes())?;
+            strings.write(&mut pages, &page.rendered_avif)?;
+            strings.write(&mut pages, &page.rendered_jpeg)?;
+            pages.write_all(&page.width.to_le_bytes())?;
+            pages.write_all(&page.height.to_le_bytes())?;
+            pages.write_all(&length.to_le_bytes())?;
         }
 
-        w.write_all(&(self.pages.len() as u32).to_le_bytes())?;
-        for page in self.pages.iter() {
-            w.write_all(&page.document_index.to_le_bytes())?;
-            w.write_all(&page.page_nr.to_le_bytes())?;
-            w.write_all(&(page.rendered_avif.len() as u32).to_le_bytes())?;
-            w.write_all(page.rendered_avif.as_bytes())?;
-            w.write_all(&(page.rendered_jpeg.len() as u32).to_le_bytes())?;
-            w.write_all(page.rendered_jpeg.as_bytes())?;
-            w.write_all(&page.width.to_le_bytes())?;
-            w.write_all(&page.height.to_le_bytes())?;
+        let mut results = Vec::new();
+        for (i, r) in self.results.iter().enumerate() {
+            let outline_entry = self.result_outline.get(i).copied().flatten();
+            results.write_all(&r.page_index.to_le_bytes())?;
+            results.write_all(&r.x.to_le_bytes())?;
+            results.write_all(&r.y.to_le_bytes())?;
+            results.write_all(&r.width.to_le_bytes())?;
+            results.write_all(&r.height.to_le_bytes())?;
+            results.write_all(&outline_entry.unwrap_or(NO_OUTLINE_ENTRY).to_le_bytes())?;
         }
 
-        w.write_all(&(self.results.len() as u32).to_le_bytes())?;
-        for r in self.results.iter() {
-            w.write_all(&r.page_index.to_le_bytes())?;
-            w.write_all(&r.x.to_le_bytes())?;
-            w.write_all(&r.y.to_le_bytes())?;
-            w.write_all(&r.width.to_le_bytes())?;
-            w.write_all(&r.height.to_le_bytes())?;
+        let mut outline = Vec::new();
+        for entry in self.outline.iter() {
+            outline.write_all(&entry.document_index.to_le_bytes())?;
+            outline.write_all(&entry.depth.to_le_bytes())?;
+            outline.write_all(&entry.parent.unwrap_or(NO_OUTLINE_ENTRY).to_le_bytes())?;
+            strings.write(&mut outline, &entry.title)?;
         }
 
-        w.write_all(&(self.words.len() as u32).to_le_bytes())?;
+        // The words are sorted because they come from a `BTreeMap`, as needed to build the
+        // dictionary.
+        let mut words = fst::MapBuilder::memory();
+        let mut postings = Vec::new();
         for (word, matches) in self.words.iter() {
-            w.write_all(&(word.len() as u32).to_le_bytes())?;
-            w.write_all(word.as_bytes())?;
-            w.write_all(&(matches.len() as u32).to_le_bytes())?;
-            for m in matches.iter() {
-                w.write_all(&m.result_index.to_le_bytes())?;
-                w.write_all(&m.score.to_le_bytes())?;
-            }
+            let start = postings.len();
+            encode_postings(matches, self.page_frequency(matches), &mut postings);
+            words
+                .insert(word, pack_postings_position(start, matches.len()))
+                .map_err(io::Error::other)?;
+        }
+        let words = words
+            .into_inner()
+            .map_err(io::Error::other)?;
+
+        let mut sorted_word_boxes: Vec<_> = self
+            .word_boxes
+            .iter()
+            .map(|b| (b.result_index, word_hash(&b.word), b.x, b.width))
+            .collect();
+        sorted_word_boxes.sort_unstable();
+        let mut word_boxes = Vec::new();
+        for (result_index, hash, x, width) in sorted_word_boxes {
+            word_boxes.write_all(&result_index.to_le_bytes())?;
+            word_boxes.write_all(&hash.to_le_bytes())?;
+            word_boxes.write_all(&x.to_le_bytes())?;
+            word_boxes.write_all(&width.to_le_bytes())?;
         }
 
+        let mut sorted_word_positions: Vec<_> = self
+            .word_positions
+            .iter()
+            .map(|p| (p.result_index, word_hash(&p.word), p.position))
+            .collect();
+        sorted_word_positions.sort_unstable();
+        let mut word_positions = Vec::new();
+        for (result_index, hash, position) in sorted_word_positions {
+            word_positions.write_all(&result_index.to_le_bytes())?;
+            word_positions.write_all(&hash.to_le_bytes())?;
+            word_positions.write_all(&position.to_le_bytes())?;
+        }
+
+        // Lines are separated by line breaks in a block, so they must not contain any.
+        let mut text_blocks = Vec::new();
+        let mut text = Vec::new();
+        for lines in self.result_texts.chunks(LINES_PER_TEXT_BLOCK) {
+            let block: Vec<String> = lines.iter().map(|l| l.replace('\n', " ")).collect();
+            let compressed = lz4_flex::compress_prepend_size(block.join("\n").as_bytes());
+            text_blocks.write_all(&(text.len() as u32).to_le_bytes())?;
+            text_blocks.write_all(&(compressed.len() as u32).to_le_bytes())?;
+            text.extend_from_slice(&compressed);
+        }
+
+        let total_length: u64 = page_lengths.iter().map(|l| *l as u64).sum();
+        let average_page_length = total_length as f32 / self.pages.len().max(1) as f32;
+        let statistics = average_page_length.to_le_bytes().to_vec();
+
+        let sections = [
+            (SECTION_DOCUMENTS, documents),
+            (SECTION_PAGES, pages),
+            (SECTION_RESULTS, results),
+            (SECTION_WORDS, words),
+            (SECTION_POSTINGS, postings),
+            (SECTION_STRINGS, strings.0),
+            (SECTION_STATISTICS, statistics),
+            (SECTION_WORD_BOXES, word_boxes),
+            (SECTION_TEXT_BLOCKS, text_blocks),
+            (SECTION_TEXT, text),
+            (SECTION_OUTLINE, outline),
+            (SECTION_WORD_POSITIONS, word_positions),
+        ];
+
+        let mut header = Vec::new();
+        header.write_all(&MAGIC)?;
+        header.write_all(&FORMAT_VERSION.to_le_bytes())?;
+        header.write_all(&self.metadata.build_timestamp.to_le_bytes())?;
+        header.write_all(&(self.metadata.generator_settings.len() as u32).to_le_bytes())?;
+        for (key, value) in self.metadata.generator_settings.iter() {
+            header.write_all(&(key.len() as u32).to_le_bytes())?;
+            header.write_all(key.as_bytes())?;
+            header.write_all(&(value.len() as u32).to_le_bytes())?;
+            header.write_all(value.as_bytes())?;
+        }
+        header.write_all(&(sections.len() as u32).to_le_bytes())?;
+        let mut offset = (header.len() + sections.len() * (4 + 8 + 8)) as u64;
+        for (id, data) in sections.iter() {
+            header.write_all(&id.to_le_bytes())?;
+            header.write_all(&offset.to_le_bytes())?;
+            header.write_all(&(data.len() as u64).to_le_bytes())?;
+            offset += data.len() as u64;
+        }
+
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&header);
+        w.write_all(&header)?;
+        for (_, data) in sections.iter() {
+            hasher.update(data);
+            w.write_all(data)?;
+        }
+        w.write_all(&hasher.finalize().to_le_bytes())?;
+
         Ok(())
     }
+
+    /// Compares the size of the postings with the current encoding to the fixed-size one.
+    pub fn postings_size_report(&self) -> PostingsSizeReport {
+        let match_count = self.words.values().map(|m| m.len()).sum();
+        let mut compressed = Vec::new();
+        for matches in self.words.values() {
+            encode_postings(matches, self.page_frequency(matches), &mut compressed);
+        }
+        PostingsSizeReport {
+            match_count,
+            fixed_size: match_count * 8,
+            compressed_size: compressed.len(),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::io::Write;
+
+    use crate::normalize::Language;
+
+    use super::{
+        Document, Match, OutlineEntry, Page, SearchIndex, SearchResult, WordBox, WordPosition,
+    };
+
+    pub(crate) fn sample_index() -> SearchIndex {
+        let mut index = SearchIndex::new();
+        index.metadata.build_timestamp = 1646000000;
+        index
+            .metadata
+            .generator_settings
+            .insert("scale".to_owned(), "1.8".to_owned());
+        index.documents.push(Document {
+            name: "michelson.pdf".to_owned(),
+            subject: "physique".to_owned(),
+            chapter: None,
+            language: Language::English,
+        });
+        index.pages.push(Page {
+            document_index: 0,
+            page_nr: 3,
+            rendered_avif: "avif".to_owned(),
+            rendered_jpeg: "jpeg".to_owned(),
+            width: 1000,
+            height: 1400,
+        });
+        index.results.push(SearchResult {
+            page_index: 0,
+            x: 10,
+            y: 20,
+            width: 300,
+            height: 15,
+        });
+        index.words.insert(
+            "interferometr".to_owned(),
+            vec![Match {
+                result_index: 0,
+                score: 1.5,
+            }],
+        );
+        index.word_boxes.push(WordBox {
+            result_index: 0,
+            word: "interferometr".to_owned(),
+            x: 110,
+            width: 90,
+        });
+        index.word_positions.push(WordPosition {
+            result_index: 0,
+            word: "interferometr".to_owned(),
+            position: 0,
+        });
+        index
+            .result_texts
+            .push("Interféromètre de Michelson".to_owned());
+        index.outline.push(OutlineEntry {
+            document_index: 0,
+            parent: None,
+            depth: 0,
+            title: "Interférences".to_owned(),
+        });
+        index.outline.push(OutlineEntry {
+            document_index: 0,
+            parent: Some(0),
+            depth: 1,
+            title: "Division d'amplitude".to_owned(),
+        });
+        index.result_outline.push(Some(1));
+        index
+    }
+
+    #[test]
+    fn headerless() {
+        let mut bytes = Vec::new();
+        bytes.write_all(&1u32.to_le_bytes()).unwrap();
+        bytes.write_all(&13u32.to_le_bytes()).unwrap();
+        bytes.write_all(b"michelson.pdf").unwrap();
+        // No pages or results.
+        bytes.write_all(&0u32.to_le_bytes()).unwrap();
+        bytes.write_all(&0u32.to_le_bytes()).unwrap();
+        bytes.write_all(&1u32.to_le_bytes()).unwrap();
+        bytes.write_all(&2u32.to_le_bytes()).unwrap();
+        bytes.write_all(b"cs").unwrap();
+        bytes.write_all(&1u32.to_le_bytes()).unwrap();
+        bytes.write_all(&7u32.to_le_bytes()).unwrap();
+        bytes.write_all(&1.25f32.to_le_bytes()).unwrap();
+
+        let index = SearchIndex::deserialize_headerless(&mut &bytes[..]).unwrap();
+        assert_eq!(index.documents[0].name, "michelson.pdf");
+        assert_eq!(index.words["cs"][0].result_index, 7);
+        assert_eq!(index.words["cs"][0].score, 1.25);
+    }
 }
@@ -1,3 +1,9 @@
+pub mod fuzzy;
 pub mod index;
+pub mod mapped;
 pub mod normalize;
+pub mod postings;
+pub mod query;
 pub mod search;
+pub mod suggest;
+pub mod synonyms;
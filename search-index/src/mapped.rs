@@ -0,0 +1,708 @@
+//! A read-only view of a serialized search index that is queried in place.
+
+use std::{fs::File, ops::{Bound, Range}, path::Path, str, sync::Arc};
+
+use fst::{
+    automaton::{Levenshtein, Str},
+    map::Stream,
+    Automaton, IntoStreamer, Map, Streamer,
+};
+use memmap2::Mmap;
+
+use crate::{
+    index::{
+        parse_header, word_hash, IndexError, IndexMetadata, SearchResult, DOCUMENT_RECORD_SIZE,
+        LINES_PER_TEXT_BLOCK, NO_CHAPTER, NO_OUTLINE_ENTRY, OUTLINE_RECORD_SIZE, PAGE_RECORD_SIZE,
+        RESULT_RECORD_SIZE, SECTION_DOCUMENTS, SECTION_OUTLINE, SECTION_PAGES, SECTION_POSTINGS,
+        SECTION_RESULTS, SECTION_STATISTICS, SECTION_STRINGS, SECTION_TEXT, SECTION_TEXT_BLOCKS,
+        SECTION_WORDS, SECTION_WORD_BOXES, STATISTICS_RECORD_SIZE, TEXT_BLOCK_RECORD_SIZE,
+        SECTION_WORD_POSITIONS, WORD_BOX_RECORD_SIZE, WORD_POSITION_RECORD_SIZE,
+    },
+    normalize::Language,
+    postings::Postings,
+    synonyms::{Synonyms, QUERY_SYNONYMS, SYNONYMS_SETTING},
+};
+
+/// Returns the offsets of the records of a word in a line, in a section of records that start
+/// with a result index and a word hash and are sorted by them.
+fn word_record_offsets<'a>(
+    records: &'a [u8],
+    record_size: usize,
+    result_index: u32,
+    word: &str,
+) -> impl Iterator<Item = usize> + 'a {
+    let key = (result_index, word_hash(word));
+    let record_key = move |i: usize| {
+        let offset = i * record_size;
+        (read_u32(records, offset), read_u32(records, offset + 4))
+    };
+    let count = records.len() / record_size;
+    let (mut low, mut high) = (0, count);
+    while low < high {
+        let mid = (low + high) / 2;
+        if record_key(mid) < key {
+            low = mid + 1;
+        } else {
+            high = mid;
+        }
+    }
+    (low..count).map_while(move |i| (record_key(i) == key).then_some(i * record_size))
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(bytes[offset..(offset + 2)].try_into().unwrap())
+}
+
+fn read_i16(bytes: &[u8], offset: usize) -> i16 {
+    i16::from_le_bytes(bytes[offset..(offset + 2)].try_into().unwrap())
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..(offset + 4)].try_into().unwrap())
+}
+
+enum Storage {
+    Mapped(Mmap),
+    Owned(Vec<u8>),
+}
+
+impl Storage {
+    fn bytes(&self) -> &[u8] {
+        match self {
+            Storage::Mapped(m) => m,
+            Storage::Owned(v) => v,
+        }
+    }
+}
+
+/// A section of the storage, which the word dictionary can own.
+struct SectionBytes {
+    storage: Arc<Storage>,
+    range: Range<usize>,
+}
+
+impl AsRef<[u8]> for SectionBytes {
+    fn as_ref(&self) -> &[u8] {
+        &self.storage.bytes()[self.range.clone()]
+    }
+}
+
+/// A document of a `MappedSearchIndex`.
+pub struct DocumentRef<'a> {
+    pub name: &'a str,
+    pub subject: &'a str,
+    pub chapter: Option<u16>,
+    pub language: Language,
+}
+
+/// A page of a `MappedSearchIndex`.
+pub struct PageRef<'a> {
+    pub document_index: u16,
+    pub page_nr: u16,
+    pub rendered_avif: &'a str,
+    pub rendered_jpeg: &'a str,
+    pub width: u16,
+    pub height: u16,
+    /// Number of matches on the page, see `average_page_length`.
+    pub length: u32,
+}
+
+/// An outline entry of a `MappedSearchIndex`.
+pub struct OutlineEntryRef<'a> {
+    pub document_index: u16,
+    pub parent: Option<u32>,
+    pub depth: u16,
+    pub title: &'a str,
+}
+
+pub struct MappedSearchIndex {
+    storage: Arc<Storage>,
+    metadata: IndexMetadata,
+    documents: Range<usize>,
+    pages: Range<usize>,
+    results: Range<usize>,
+    /// Maps the words to the position of their matches in the postings section.
+    words: Map<SectionBytes>,
+    postings: Range<usize>,
+    strings: Range<usize>,
+    word_boxes: Range<usize>,
+    word_positions: Range<usize>,
+    text_blocks: Range<usize>,
+    text: Range<usize>,
+    outline: Range<usize>,
+    average_page_length: f32,
+}
+
+impl MappedSearchIndex {
+    /// Memory maps a search index file.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, IndexError> {
+        let file = File::open(path)?;
+        // SAFETY: the file must not be modified while it is mapped, which is why the generator
+        // replaces it with a rename instead of writing to it.
+        let mmap = unsafe { Mmap::map(&file)? };
+        Self::new(Storage::Mapped(mmap))
+    }
+
+    /// Queries a search index that is already in memory.
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, IndexError> {
+        Self::new(Storage::Owned(bytes))
+    }
+
+    fn new(storage: Storage) -> Result<Self, IndexError> {
+        let storage = Arc::new(storage);
+        let (metadata, sections) = parse_header(storage.bytes())?;
+        let section = |id, record_size| -> Result<Range<usize>, IndexError> {
+            let (_, offset, len) = sections
+                .iter()
+                .find(|(section_id, _, _)| *section_id == id)
+                .ok_or(IndexError::Corrupted("missing section"))?;
+            if !(*len as usize).is_multiple_of(record_size) {
+                return Err(IndexError::Corrupted("truncated section"));
+            }
+            Ok((*offset as usize)..((offset + len) as usize))
+        };
+
+        let statistics = section(SECTION_STATISTICS, STATISTICS_RECORD_SIZE)?;
+        if statistics.len() != STATISTICS_RECORD_SIZE {
+            return Err(IndexError::Corrupted("invalid statistics"));
+        }
+        let average_page_length =
+            f32::from_le_bytes(storage.bytes()[statistics].try_into().unwrap());
+
+        let index = Self {
+            metadata,
+            documents: section(SECTION_DOCUMENTS, DOCUMENT_RECORD_SIZE)?,
+            pages: section(SECTION_PAGES, PAGE_RECORD_SIZE)?,
+            results: section(SECTION_RESULTS, RESULT_RECORD_SIZE)?,
+            words: Map::new(SectionBytes {
+                storage: storage.clone(),
+                range: section(SECTION_WORDS, 1)?,
+            })
+            .map_err(|_| IndexError::Corrupted("invalid word dictionary"))?,
+            postings: section(SECTION_POSTINGS, 1)?,
+            strings: section(SECTION_STRINGS, 1)?,
+            word_boxes: section(SECTION_WORD_BOXES, WORD_BOX_RECORD_SIZE)?,
+            word_positions: section(SECTION_WORD_POSITIONS, WORD_POSITION_RECORD_SIZE)?,
+            text_blocks: section(SECTION_TEXT_BLOCKS, TEXT_BLOCK_RECORD_SIZE)?,
+            text: section(SECTION_TEXT, 1)?,
+            outline: section(SECTION_OUTLINE, OUTLINE_RECORD_SIZE)?,
+            average_page_length,
+            storage,
+        };
+        index.validate()?;
+        Ok(index)
+    }
+
+    /// Checks that the references between sections are in bounds, so that accessors can't read
+    /// outside of a section or return invalid UTF-8.
+    fn validate(&self) -> Result<(), IndexError> {
+        let strings = &self.storage.bytes()[self.strings.clone()];
+        let check_string = |section: &[u8], offset| {
+            let start = read_u32(section, offset) as usize;
+            let len = read_u32(section, offset + 4) as usize;
+            strings
+                .get(start..(start + len))
+                .and_then(|s| str::from_utf8(s).ok())
+                .map(|_| ())
+                .ok_or(IndexError::Corrupted("invalid string"))
+        };
+
+        let documents = self.section(&self.documents);
+        for i in 0..self.document_count() {
+            check_string(documents, i * DOCUMENT_RECORD_SIZE)?;
+            check_string(documents, i * DOCUMENT_RECORD_SIZE + 8)?;
+            Language::from_id(read_u16(documents, i * DOCUMENT_RECORD_SIZE + 18))
+                .ok_or(IndexError::Corrupted("invalid language"))?;
+        }
+        let pages = self.section(&self.pages);
+        for i in 0..self.page_count() {
+            if read_u16(pages, i * PAGE_RECORD_SIZE) as usize >= self.document_count() {
+                return Err(IndexError::Corrupted("invalid page"));
+            }
+            check_string(pages, i * PAGE_RECORD_SIZE + 4)?;
+            check_string(pages, i * PAGE_RECORD_SIZE + 12)?;
+        }
+        let outline = self.section(&self.outline);
+        for i in 0..self.outline_entry_count() {
+            // Parents must come first, so that walking up the outline always ends.
+            let parent = read_u32(outline, i * OUTLINE_RECORD_SIZE + 4);
+            if parent != NO_OUTLINE_ENTRY && parent as usize >= i {
+                return Err(IndexError::Corrupted("invalid outline entry"));
+            }
+            check_string(outline, i * OUTLINE_RECORD_SIZE + 8)?;
+        }
+        let results = self.section(&self.results);
+        for i in 0..self.result_count() {
+            if read_u32(results, i * RESULT_RECORD_SIZE) as usize >= self.page_count() {
+                return Err(IndexError::Corrupted("invalid result"));
+            }
+            let entry = read_u32(results, i * RESULT_RECORD_SIZE + 12);
+            if entry != NO_OUTLINE_ENTRY && entry as usize >= self.outline_entry_count() {
+                return Err(IndexError::Corrupted("invalid outline entry"));
+            }
+        }
+        let postings = self.section(&self.postings);
+        let mut words = self.words.stream();
+        while let Some((word, value)) = words.next() {
+            let (start, count) = unpack_postings_position(value);
+            let valid = str::from_utf8(word).is_ok()
+                && postings
+                    .get(start..)
+                    .map(|bytes| Postings::is_valid(bytes, count, self.result_count()))
+                    .unwrap_or(false);
+            if !valid {
+                return Err(IndexError::Corrupted("invalid postings"));
+            }
+        }
+        let word_boxes = self.section(&self.word_boxes);
+        for i in 0..(word_boxes.len() / WORD_BOX_RECORD_SIZE) {
+            if read_u32(word_boxes, i * WORD_BOX_RECORD_SIZE) as usize >= self.result_count() {
+                return Err(IndexError::Corrupted("invalid word box"));
+            }
+        }
+        let word_positions = self.section(&self.word_positions);
+        for i in 0..(word_positions.len() / WORD_POSITION_RECORD_SIZE) {
+            let result_index = read_u32(word_positions, i * WORD_POSITION_RECORD_SIZE);
+            if result_index as usize >= self.result_count() {
+                return Err(IndexError::Corrupted("invalid word position"));
+            }
+        }
+        // The content of the blocks is only checked when they are decompressed.
+        let text_blocks = self.section(&self.text_blocks);
+        for i in 0..(text_blocks.len() / TEXT_BLOCK_RECORD_SIZE) {
+            let start = read_u32(text_blocks, i * TEXT_BLOCK_RECORD_SIZE) as usize;
+            let len = read_u32(text_blocks, i * TEXT_BLOCK_RECORD_SIZE + 4) as usize;
+            if start + len > self.text.len() {
+                return Err(IndexError::Corrupted("invalid text block"));
+            }
+        }
+        Ok(())
+    }
+
+    fn section(&self, range: &Range<usize>) -> &[u8] {
+        &self.storage.bytes()[range.clone()]
+    }
+
+    fn string(&self, record: &[u8]) -> &str {
+        let start = self.strings.start + read_u32(record, 0) as usize;
+        let len = read_u32(record, 4) as usize;
+        // SAFETY: all strings were checked to be valid UTF-8 in `validate`.
+        unsafe { str::from_utf8_unchecked(&self.storage.bytes()[start..(start + len)]) }
+    }
+
+    pub fn metadata(&self) -> &IndexMetadata {
+        &self.metadata
+    }
+
+    /// Whether the words of the index are not canonicalized, so that the search must expand the
+    /// synonyms of the queries, see `SearchOptions::expand_synonyms`.
+    pub fn has_query_synonyms(&self) -> bool {
+        self.metadata.generator_settings.get(SYNONYMS_SETTING).map(String::as_str)
+            == Some(QUERY_SYNONYMS)
+    }
+
+    /// Explains why the words of queries normalized with `synonyms` may not match the words of
+    /// the index, if they may not.
+    pub fn synonyms_mismatch(&self, synonyms: &Synonyms) -> Option<String> {
+        if self.has_query_synonyms() {
+            return None;
+        }
+        let fingerprint = synonyms.fingerprint();
+        match self.metadata.generator_settings.get(SYNONYMS_SETTING) {
+            Some(f) if *f == fingerprint => None,
+            Some(f) => Some(format!(
+                "the index was built with synonyms {} but the queries use {}; set SYNONYMS_FILE \
+                 to the file given to the generator or regenerate the index",
+                f, fingerprint
+            )),
+            None => Some("the index doesn't record which synonyms it was built with".to_owned()),
+        }
+    }
+
+    pub fn document_count(&self) -> usize {
+        self.documents.len() / DOCUMENT_RECORD_SIZE
+    }
+
+    pub fn document(&self, index: usize) -> DocumentRef<'_> {
+        let offset = index * DOCUMENT_RECORD_SIZE;
+        let record = &self.section(&self.documents)[offset..(offset + DOCUMENT_RECORD_SIZE)];
+        let chapter = read_u16(record, 16);
+        DocumentRef {
+            name: self.string(&record[0..8]),
+            subject: self.string(&record[8..16]),
+            chapter: if chapter == NO_CHAPTER { None } else { Some(chapter) },
+            // Checked by `validate`.
+            language: Language::from_id(read_u16(record, 18)).unwrap(),
+        }
+    }
+
+    pub fn page_count(&self) -> usize {
+        self.pages.len() / PAGE_RECORD_SIZE
+    }
+
+    pub fn page(&self, index: usize) -> PageRef<'_> {
+        let offset = index * PAGE_RECORD_SIZE;
+        let record = &self.section(&self.pages)[offset..(offset + PAGE_RECORD_SIZE)];
+        PageRef {
+            document_index: read_u16(record, 0),
+            page_nr: read_u16(record, 2),
+            rendered_avif: self.string(&record[4..12]),
+            rendered_jpeg: self.string(&record[12..20]),
+            width: read_u16(record, 20),
+            height: read_u16(record, 22),
+            length: read_u32(record, 24),
+        }
+    }
+
+    /// Returns the average number of matches on a page, each line counting once for each
+    /// distinct word that it contains.
+    pub fn average_page_length(&self) -> f32 {
+        self.average_page_length
+    }
+
+    pub fn result_count(&self) -> usize {
+        self.results.len() / RESULT_RECORD_SIZE
+    }
+
+    pub fn result(&self, index: usize) -> SearchResult {
+        let offset = index * RESULT_RECORD_SIZE;
+        let record = &self.section(&self.results)[offset..(offset + RESULT_RECORD_SIZE)];
+        SearchResult {
+            page_index: read_u32(record, 0),
+            x: read_i16(record, 4),
+            y: read_i16(record, 6),
+            width: read_u16(record, 8),
+            height: read_u16(record, 10),
+        }
+    }
+
+    /// Returns the innermost outline entry that contains a result.
+    pub fn result_outline_entry(&self, index: usize) -> Option<u32> {
+        let offset = index * RESULT_RECORD_SIZE;
+        let entry = read_u32(self.section(&self.results), offset + 12);
+        if entry == NO_OUTLINE_ENTRY { None } else { Some(entry) }
+    }
+
+    pub fn outline_entry_count(&self) -> usize {
+        self.outline.len() / OUTLINE_RECORD_SIZE
+    }
+
+    pub fn outline_entry(&self, index: usize) -> OutlineEntryRef<'_> {
+        let offset = index * OUTLINE_RECORD_SIZE;
+        let record = &self.section(&self.outline)[offset..(offset + OUTLINE_RECORD_SIZE)];
+        let parent = read_u32(record, 4);
+        OutlineEntryRef {
+            document_index: read_u16(record, 0),
+            parent: if parent == NO_OUTLINE_ENTRY { None } else { Some(parent) },
+            depth: read_u16(record, 2),
+            title: self.string(&record[8..16]),
+        }
+    }
+
+    /// Returns the titles of the outline entries that contain a result, outermost first.
+    pub fn breadcrumb(&self, result_index: usize) -> Vec<&str> {
+        let mut titles = Vec::new();
+        let mut entry = self.result_outline_entry(result_index);
+        while let Some(e) = entry {
+            let outline_entry = self.outline_entry(e as usize);
+            titles.push(outline_entry.title);
+            entry = outline_entry.parent;
+        }
+        titles.reverse();
+        titles
+    }
+
+    /// Returns the original text of a result, if the index has it.
+    pub fn result_text(&self, index: usize) -> Option<String> {
+        let offset = index / LINES_PER_TEXT_BLOCK * TEXT_BLOCK_RECORD_SIZE;
+        let record = self
+            .section(&self.text_blocks)
+            .get(offset..(offset + TEXT_BLOCK_RECORD_SIZE))?;
+        let start = read_u32(record, 0) as usize;
+        let len = read_u32(record, 4) as usize;
+        let compressed = &self.section(&self.text)[start..(start + len)];
+        let block = lz4_flex::decompress_size_prepended(compressed).ok()?;
+        let block = String::from_utf8(block).ok()?;
+        block
+            .split('\n')
+            .nth(index % LINES_PER_TEXT_BLOCK)
+            .map(|line| line.to_owned())
+    }
+
+    /// Returns the horizontal position and the width of the occurrences of a word in a line, if
+    /// the generator recorded them.
+    pub fn word_boxes<'a>(
+        &'a self,
+        result_index: u32,
+        word: &str,
+    ) -> impl Iterator<Item = (i16, u16)> + 'a {
+        let records = self.section(&self.word_boxes);
+        word_record_offsets(records, WORD_BOX_RECORD_SIZE, result_index, word)
+            .map(|offset| (read_i16(records, offset + 8), read_u16(records, offset + 10)))
+    }
+
+    /// Whether the index has word positions, which indexes in the legacy layout don't.
+    pub fn has_word_positions(&self) -> bool {
+        !self.word_positions.is_empty()
+    }
+
+    /// Returns the positions of the occurrences of a word among the words of a line, in
+    /// increasing order.
+    pub fn word_positions<'a>(
+        &'a self,
+        result_index: u32,
+        word: &str,
+    ) -> impl Iterator<Item = u16> + 'a {
+        let records = self.section(&self.word_positions);
+        word_record_offsets(records, WORD_POSITION_RECORD_SIZE, result_index, word)
+            .map(|offset| read_u16(records, offset + 8))
+    }
+
+    pub fn word_count(&self) -> usize {
+        self.words.len()
+    }
+
+    fn postings_at(&self, value: u64) -> Postings<'_> {
+        let (start, count) = unpack_postings_position(value);
+        Postings::new(&self.section(&self.postings)[start..], count)
+    }
+
+    /// Returns the matches of a word, if it is in the index.
+    pub fn postings(&self, word: &str) -> Option<Postings<'_>> {
+        self.words.get(word).map(|v| self.postings_at(v))
+    }
+
+    /// Returns the words that start with `prefix` in lexicographic order, with their matches.
+    pub fn words_with_prefix<'a>(
+        &'a self,
+        prefix: &'a str,
+    ) -> impl Iterator<Item = (String, Postings<'a>)> + 'a {
+        Words {
+            index: self,
+            stream: self.words.search(Str::new(prefix).starts_with()).into_stream(),
+        }
+    }
+
+    /// Returns the words between two bounds in lexicographic order, with their matches.
+    pub fn words_in_range<'a>(
+        &'a self,
+        start: Bound<&str>,
+        end: Bound<&str>,
+    ) -> impl Iterator<Item = (String, Postings<'a>)> + 'a {
+        let mut range = self.words.range();
+        range = match start {
+            Bound::Included(s) => range.ge(s),
+            Bound::Excluded(s) => range.gt(s),
+            Bound::Unbounded => range,
+        };
+        range = match end {
+            Bound::Included(e) => range.le(e),
+            Bound::Excluded(e) => range.lt(e),
+            Bound::Unbounded => range,
+        };
+        Words {
+            index: self,
+            stream: range.into_stream(),
+        }
+    }
+
+    /// Returns the words that are at most `distance` edits away from `word`, with their matches,
+    /// or `None` if the automaton would be too big.
+    pub fn words_within_distance<'a>(
+        &'a self,
+        word: &str,
+        distance: u32,
+    ) -> Option<impl Iterator<Item = (String, Postings<'a>)> + 'a> {
+        let automaton = Levenshtein::new(word, distance).ok()?;
+        Some(Words {
+            index: self,
+            stream: self.words.search(automaton).into_stream(),
+        })
+    }
+
+    /// Like `words_within_distance`, but returns the words that start with a prefix that is at
+    /// most `distance` edits away from `prefix`.
+    pub fn words_with_prefix_within_distance<'a>(
+        &'a self,
+        prefix: &str,
+        distance: u32,
+    ) -> Option<impl Iterator<Item = (String, Postings<'a>)> + 'a> {
+        let automaton = Levenshtein::new(prefix, distance).ok()?.starts_with();
+        Some(Words {
+            index: self,
+            stream: self.words.search(automaton).into_stream(),
+        })
+    }
+}
+
+/// The value associated to a word in the dictionary is the offset of its matches in the postings
+/// section in the high 32 bits and their count in the low 32 bits.
+pub(crate) fn pack_postings_position(start: usize, count: usize) -> u64 {
+    ((start as u64) << 32) | count as u64
+}
+
+fn unpack_postings_position(value: u64) -> (usize, usize) {
+    ((value >> 32) as usize, (value & 0xffff_ffff) as usize)
+}
+
+/// An iterator over words of a `MappedSearchIndex` accepted by an automaton, and their matches.
+struct Words<'a, A: Automaton> {
+    index: &'a MappedSearchIndex,
+    stream: Stream<'a, A>,
+}
+
+impl<'a, A: Automaton> Iterator for Words<'a, A> {
+    type Item = (String, Postings<'a>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (word, value) = self.stream.next()?;
+        // All words were checked to be valid UTF-8 in `validate`.
+        let word = String::from_utf8_lossy(word).into_owned();
+        Some((word, self.index.postings_at(value)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Bound;
+
+    use crate::{
+        index::{tests::sample_index, IndexError, SearchResult},
+        normalize::Language,
+        synonyms::{Synonyms, SYNONYMS_SETTING},
+    };
+
+    use super::MappedSearchIndex;
+
+    fn sample_bytes() -> Vec<u8> {
+        let mut bytes = Vec::new();
+        sample_index().serialize(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn round_trip() {
+        let index = MappedSearchIndex::from_bytes(sample_bytes()).unwrap();
+        assert_eq!(index.metadata().build_timestamp, 1646000000);
+        assert_eq!(index.metadata().generator_settings["scale"], "1.8");
+        assert_eq!(index.document(0).name, "michelson.pdf");
+        assert_eq!(index.document(0).subject, "physique");
+        assert_eq!(index.document(0).chapter, None);
+        assert_eq!(index.document(0).language, Language::English);
+        assert_eq!(index.page(0).page_nr, 3);
+        assert_eq!(index.page(0).rendered_jpeg, "jpeg");
+        assert_eq!(index.page(0).length, 1);
+        assert_eq!(index.average_page_length(), 1.);
+        assert_eq!(index.result(0).width, 300);
+        let boxes: Vec<_> = index.word_boxes(0, "interferometr").collect();
+        assert_eq!(boxes, vec![(110, 90)]);
+        assert_eq!(index.word_boxes(0, "interfer").count(), 0);
+        assert_eq!(index.word_boxes(1, "interferometr").count(), 0);
+        assert!(index.has_word_positions());
+        let positions: Vec<_> = index.word_positions(0, "interferometr").collect();
+        assert_eq!(positions, vec![0]);
+        assert_eq!(index.word_positions(0, "michelson").count(), 0);
+        assert_eq!(index.result_text(0).unwrap(), "Interféromètre de Michelson");
+        assert_eq!(index.result_text(1), None);
+        assert_eq!(index.outline_entry_count(), 2);
+        assert_eq!(index.outline_entry(1).depth, 1);
+        assert_eq!(index.result_outline_entry(0), Some(1));
+        assert_eq!(index.breadcrumb(0), vec!["Interférences", "Division d'amplitude"]);
+        let matches: Vec<_> = index.postings("interferometr").unwrap().collect();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(index.postings("interferometr").unwrap().page_frequency(), 1);
+        assert!((matches[0].score - 1.5).abs() < 0.01);
+        assert!(index.postings("interfer").is_none());
+        let words: Vec<_> = index.words_with_prefix("interfer").map(|(w, _)| w).collect();
+        assert_eq!(words, vec!["interferometr"]);
+        let words: Vec<_> = index
+            .words_in_range(Bound::Included("i"), Bound::Excluded("j"))
+            .map(|(w, _)| w)
+            .collect();
+        assert_eq!(words, vec!["interferometr"]);
+        let words: Vec<_> = index
+            .words_within_distance("interferomatr", 1)
+            .unwrap()
+            .map(|(w, _)| w)
+            .collect();
+        assert_eq!(words, vec!["interferometr"]);
+        let words: Vec<_> = index
+            .words_with_prefix_within_distance("intar", 1)
+            .unwrap()
+            .map(|(w, _)| w)
+            .collect();
+        assert_eq!(words, vec!["interferometr"]);
+    }
+
+    #[test]
+    fn synonyms_mismatch() {
+        let index = MappedSearchIndex::from_bytes(sample_bytes()).unwrap();
+        assert!(index.synonyms_mismatch(&Synonyms::builtin()).is_some());
+
+        let mut index = sample_index();
+        let settings = &mut index.metadata.generator_settings;
+        settings.insert(SYNONYMS_SETTING.to_owned(), Synonyms::builtin().fingerprint());
+        let mut bytes = Vec::new();
+        index.serialize(&mut bytes).unwrap();
+        let index = MappedSearchIndex::from_bytes(bytes).unwrap();
+        assert!(index.synonyms_mismatch(&Synonyms::builtin()).is_none());
+        assert!(index.synonyms_mismatch(Synonyms::none()).is_some());
+    }
+
+    #[test]
+    fn rejects_bad_files() {
+        let bytes = sample_bytes();
+
+        let truncated = bytes[..bytes.len() - 10].to_vec();
+        assert!(matches!(
+            MappedSearchIndex::from_bytes(truncated),
+            Err(IndexError::Corrupted(_))
+        ));
+
+        let mut flipped = bytes.clone();
+        flipped[40] ^= 1;
+        assert!(matches!(
+            MappedSearchIndex::from_bytes(flipped),
+            Err(IndexError::Corrupted(_))
+        ));
+
+        let mut future = bytes;
+        future[8] = 255;
+        assert!(matches!(
+            MappedSearchIndex::from_bytes(future),
+            Err(IndexError::UnsupportedVersion(255))
+        ));
+
+        assert!(matches!(
+            MappedSearchIndex::from_bytes(b"\x01\x00\x00\x00".to_vec()),
+            Err(IndexError::WrongMagic)
+        ));
+    }
+
+    #[test]
+    fn rejects_dangling_indices() {
+        let mut index = sample_index();
+        index.pages[0].document_index = 1;
+        let mut bytes = Vec::new();
+        index.serialize(&mut bytes).unwrap();
+        assert!(matches!(
+            MappedSearchIndex::from_bytes(bytes),
+            Err(IndexError::Corrupted("invalid page"))
+        ));
+
+        let mut index = sample_index();
+        index.results.push(SearchResult {
+            page_index: 1,
+            x: 0,
+            y: 0,
+            width: 0,
+            height: 0,
+        });
+        let mut bytes = Vec::new();
+        index.serialize(&mut bytes).unwrap();
+        assert!(matches!(
+            MappedSearchIndex::from_bytes(bytes),
+            Err(IndexError::Corrupted("invalid result"))
+        ));
+    }
+}
@@ -1,75 +1,224 @@
+//! Normalization of the words of the lessons and of the queries, which depends on the language of
+//! the text.
+
+use std::{ops::Range, sync::OnceLock};
+
 use rust_stemmers::{Algorithm, Stemmer};
 
-include!(concat!(env!("OUT_DIR"), "/synonyms.rs"));
+use crate::synonyms::Synonyms;
 
-pub fn normalize_and_extract_words(s: &str) -> Vec<String> {
-    let stemmer = Stemmer::create(Algorithm::French);
-    let ascii = deunicode::deunicode(s);
-    let ascii_without_symbols: String = ascii.chars()
-        // Don't replace dots by space because we want to remove dots later, so
-        // that acronyms can work.
-        .map(|c| if c.is_ascii_punctuation() && c != '.' { ' ' } else { c })
-        .filter(|c| c.is_ascii_alphanumeric() || c.is_ascii_whitespace())
-        .collect();
-    let filtered_words = ascii_without_symbols
-        .split(|c: char| !c.is_ascii_alphanumeric())
-        .map(|w| {
-            let w = w.to_lowercase();
-            // The stemmer converts "cs" (Cauchy-Schwarz) into "c" which we do not want.
-            if w == "cs" {
-                w
-            } else {
-                stemmer.stem(&w).to_string()
-            }
+/// Stems of the French words that are too common to be searched.
+const FRENCH_STOP_WORDS: &[&str] = &[
+    "le", "la", "de", "un", "et", "en", "que", "dan", "pour", "ce", "qui", "ne", "se", "sur", "pas",
+    "par", "on", "mais", "ou", "comm", "il", "du", "lorsqu", "une", "est", "sont",
+];
+
+/// Stems of the English words that are too common to be searched.
+const ENGLISH_STOP_WORDS: &[&str] = &[
+    "the", "an", "of", "and", "to", "in", "is", "it", "that", "for", "on", "as", "with", "by",
+    "be", "are", "this", "or", "at", "from", "which", "when", "if", "then",
+];
+
+/// Words that are kept as they are, because the stemmers mangle them.
+const PROTECTED_WORDS: &[&str] = &[
+    // The stemmers convert "cs" (Cauchy-Schwarz) into "c".
+    "cs",
+];
+
+/// The language of a text, which selects how its words are normalized.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Language {
+    #[default]
+    French,
+    English,
+}
+
+impl Language {
+    pub const ALL: [Self; 2] = [Self::French, Self::English];
+
+    /// Recognizes a language code such as "fr" or "en", or the name of a language in English
+    /// or in French.
+    pub fn parse(s: &str) -> Option<Self> {
+        match deunicode::deunicode(s).to_lowercase().as_str() {
+            "fr" | "french" | "francais" => Some(Self::French),
+            "en" | "english" | "anglais" => Some(Self::English),
+            _ => None,
+        }
+    }
+
+    /// Identifies the language in the index.
+    pub(crate) fn id(self) -> u16 {
+        match self {
+            Self::French => 0,
+            Self::English => 1,
+        }
+    }
+
+    pub(crate) fn from_id(id: u16) -> Option<Self> {
+        match id {
+            0 => Some(Self::French),
+            1 => Some(Self::English),
+            _ => None,
+        }
+    }
+
+    /// Returns the default normalization of the language.
+    pub fn profile(self) -> &'static NormalizerConfig {
+        static FRENCH: OnceLock<NormalizerConfig> = OnceLock::new();
+        static ENGLISH: OnceLock<NormalizerConfig> = OnceLock::new();
+        let (profile, stop_words) = match self {
+            Self::French => (&FRENCH, FRENCH_STOP_WORDS),
+            Self::English => (&ENGLISH, ENGLISH_STOP_WORDS),
+        };
+        profile.get_or_init(|| NormalizerConfig {
+            language: self,
+            stop_words: stop_words.iter().map(|w| w.to_string()).collect(),
+            protected_words: PROTECTED_WORDS.iter().map(|w| w.to_string()).collect(),
         })
-        .filter(|w| w.len() >= 2)
-        // Ignore common words
-        .filter(|w| {
-            ![
-                "le", "la", "de", "un", "et", "en", "que", "dan", "pour", "ce", "qui",
-                "ne", "se", "sur", "pas", "par", "on", "mais", "ou", "comm", "il",
-                "du", "lorsqu", "une", "est", "sont",
-            ]
-            .contains(&w.as_str())
+    }
+}
+
+/// How the words of a text are normalized. Queries must use the language of the documents that
+/// they search.
+#[derive(Clone, Debug)]
+pub struct NormalizerConfig {
+    language: Language,
+    /// Stems of the words to ignore.
+    stop_words: Vec<String>,
+    /// Lowercase words that are not stemmed.
+    protected_words: Vec<String>,
+}
+
+impl Default for NormalizerConfig {
+    fn default() -> Self {
+        Language::default().profile().clone()
+    }
+}
+
+impl NormalizerConfig {
+    pub fn language(&self) -> Language {
+        self.language
+    }
+
+    pub(crate) fn stemmer(&self) -> Stemmer {
+        Stemmer::create(match self.language {
+            Language::French => Algorithm::French,
+            Language::English => Algorithm::English,
         })
-        .collect::<Vec<_>>()
-        .join(" ");
-
-    // Canonicalization
-    let mut canonicalized = filtered_words;
-    for (canonical, synonyms) in SYNONYMS.iter() {
-        for s in synonyms.iter() {
-            let mut search = 0;
-            while let Some(mut p) = canonicalized.get(search..).and_then(|c| c.find(s)) {
-                p += search;
-
-                search = p + s.len();
-                // Check if we are at a word boundary
-                if (p != 0 && canonicalized.as_bytes()[p - 1] != b' ')
-                    || (p + s.len() < canonicalized.len()
-                        && canonicalized.as_bytes()[p + s.len()] != b' ')
-                {
-                    continue;
-                }
-
-                canonicalized = canonicalized.get(..p).unwrap().to_owned()
-                    + canonical
-                    + canonicalized.get((p + s.len())..).unwrap();
-            }
+    }
+
+    /// Stems a lowercase word, unless it is protected.
+    pub(crate) fn stem(&self, stemmer: &Stemmer, word: &str) -> String {
+        if self.protected_words.iter().any(|w| w == word) {
+            word.to_owned()
+        } else {
+            stemmer.stem(word).to_string()
         }
     }
+}
+
+/// A normalized word of a text.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Token<'a> {
+    /// The normalized word.
+    pub word: String,
+    /// The words of the text that the token comes from, at `offsets`. The words of the canonical
+    /// form of a synonym share the text of the whole synonym.
+    pub text: &'a str,
+    /// Byte range of `text` in the text.
+    pub offsets: Range<usize>,
+    /// Index of the token among the tokens of the text. Ignored words don't count, and each word
+    /// of the canonical form of a synonym does.
+    pub position: usize,
+}
+
+/// Normalizes French text with the built-in synonyms.
+pub fn normalize_and_extract_words(s: &str) -> Vec<String> {
+    normalize_with_synonyms(s, &Synonyms::builtin())
+}
+
+/// Normalizes French text.
+pub fn normalize_with_synonyms(s: &str, synonyms: &Synonyms) -> Vec<String> {
+    normalize(s, Language::French.profile(), synonyms)
+}
 
-    let words = canonicalized
-        .split(' ')
-        .filter(|w| !w.is_empty())
-        .map(|w| w.to_owned())
-        .collect();
+/// Returns the stems of the words of `s` that are not common words, with synonyms replaced by
+/// their canonical form.
+pub fn normalize(s: &str, config: &NormalizerConfig, synonyms: &Synonyms) -> Vec<String> {
+    tokenize(s, config, synonyms).into_iter().map(|t| t.word).collect()
+}
+
+/// Splits `s` into words once transliterated to ASCII, with their byte range in `s`.
+fn split_words(s: &str) -> Vec<(String, Range<usize>)> {
+    let mut words = Vec::new();
+    let mut current: Option<(String, Range<usize>)> = None;
+    for (i, c) in s.char_indices() {
+        let mut buffer = [0; 4];
+        let ascii = if c.is_ascii() {
+            c.encode_utf8(&mut buffer)
+        } else {
+            deunicode::deunicode_char(c).unwrap_or("[?]")
+        };
+        for a in ascii.chars() {
+            if a.is_ascii_alphanumeric() {
+                let (word, range) = current.get_or_insert_with(|| (String::new(), i..i));
+                word.push(a);
+                range.end = i + c.len_utf8();
+            } else if a.is_ascii_whitespace() || (a.is_ascii_punctuation() && a != '.') {
+                words.extend(current.take());
+            }
+            // Other characters are removed without splitting words, in particular dots so that
+            // acronyms can work.
+        }
+    }
+    words.extend(current);
     words
 }
 
+/// Like `normalize`, but also returns where each word comes from.
+pub fn tokenize<'a>(s: &'a str, config: &NormalizerConfig, synonyms: &Synonyms) -> Vec<Token<'a>> {
+    let stemmer = config.stemmer();
+    let (words, offsets): (Vec<String>, Vec<Range<usize>>) = split_words(s)
+        .into_iter()
+        .map(|(w, range)| (config.stem(&stemmer, &w.to_lowercase()), range))
+        .filter(|(w, _)| w.len() >= 2)
+        // Ignore common words
+        .filter(|(w, _)| !config.stop_words.contains(w))
+        .unzip();
+
+    let mut tokens: Vec<Token> = Vec::with_capacity(words.len());
+    let mut push = |word: String, offsets: Range<usize>| {
+        tokens.push(Token {
+            word,
+            text: &s[offsets.clone()],
+            offsets,
+            position: tokens.len(),
+        })
+    };
+    let mut end = 0;
+    for (range, group) in synonyms.find(config.language, &words, false) {
+        for i in end..range.start {
+            push(words[i].clone(), offsets[i].clone());
+        }
+        let synonym_offsets = offsets[range.start].start..offsets[range.end - 1].end;
+        for w in synonyms.canonical(config.language, group).split(' ') {
+            push(w.to_owned(), synonym_offsets.clone());
+        }
+        end = range.end;
+    }
+    for i in end..words.len() {
+        push(words[i].clone(), offsets[i].clone());
+    }
+    tokens
+}
+
 #[cfg(test)]
 mod tests {
-    use super::normalize_and_extract_words;
+    use crate::synonyms::Synonyms;
+
+    use super::{
+        normalize, normalize_and_extract_words, normalize_with_synonyms, tokenize, Language,
+    };
 
     #[test]
     fn acronyms() {
@@ -84,5 +233,44 @@ mod tests {
     #[test]
     fn synonyms() {
         assert_eq!(normalize_and_extract_words("cs"), vec!["cauchy", "schwarz"]);
+
+        let synonyms = Synonyms::parse("\"théorème valeurs intermédiaires\" tvi");
+        assert_eq!(
+            normalize_with_synonyms("TVI", &synonyms),
+            normalize_and_extract_words("théorème des valeurs intermédiaires")
+        );
+        assert_eq!(normalize_with_synonyms("cs", &synonyms), vec!["cs"]);
+    }
+
+    #[test]
+    fn languages() {
+        assert_eq!(Language::parse("Anglais"), Some(Language::English));
+        assert_eq!(Language::parse("de"), None);
+
+        let english = Language::English.profile();
+        assert_eq!(
+            normalize("The laws of motion", english, Synonyms::none()),
+            vec!["law", "motion"]
+        );
+        assert_eq!(normalize("CS", english, Synonyms::none()), vec!["cs"]);
+    }
+
+    #[test]
+    fn tokens() {
+        let text = "Inégalité de CS, t.e.s.t.";
+        let tokens = tokenize(text, Language::French.profile(), &Synonyms::builtin());
+        let tokens: Vec<_> = tokens
+            .iter()
+            .map(|t| (t.word.as_str(), t.text, t.offsets.clone(), t.position))
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                ("inegalit", "Inégalité", 0..11, 0),
+                ("cauchy", "CS", 15..17, 1),
+                ("schwarz", "CS", 15..17, 2),
+                ("test", "t.e.s.t", 19..26, 3),
+            ]
+        );
     }
 }
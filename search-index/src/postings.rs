@@ -0,0 +1,186 @@
+//! Compressed encoding of the matches of a word.
+//!
+//! The number of pages of the word comes first, then the matches sorted by result index: the
+//! delta from the previous result index as a varint and the score quantized to a byte.
+
+use std::fmt;
+
+use crate::index::Match;
+
+/// Scores are quantized linearly between these values. The score of a line is 1 plus its
+/// importance, which is between 0 and 1.
+pub(crate) const MIN_SCORE: f32 = 1.;
+pub(crate) const MAX_SCORE: f32 = 2.;
+
+pub(crate) fn quantize_score(score: f32) -> u8 {
+    ((score - MIN_SCORE) / (MAX_SCORE - MIN_SCORE) * 255.).round().clamp(0., 255.) as u8
+}
+
+pub(crate) fn dequantize_score(q: u8) -> f32 {
+    MIN_SCORE + q as f32 / 255. * (MAX_SCORE - MIN_SCORE)
+}
+
+fn write_varint(out: &mut Vec<u8>, mut v: u32) {
+    while v >= 0x80 {
+        out.push((v as u8) | 0x80);
+        v >>= 7;
+    }
+    out.push(v as u8);
+}
+
+fn read_varint(bytes: &[u8]) -> Option<(u32, usize)> {
+    let mut v = 0u32;
+    for (i, b) in bytes.iter().enumerate().take(5) {
+        v |= ((b & 0x7f) as u32) << (7 * i);
+        if b & 0x80 == 0 {
+            return Some((v, i + 1));
+        }
+    }
+    None
+}
+
+/// Appends the encoded matches to `out`. The matches are sorted first.
+pub(crate) fn encode_postings(matches: &[Match], page_frequency: u32, out: &mut Vec<u8>) {
+    write_varint(out, page_frequency);
+    let mut result_indices: Vec<_> = matches
+        .iter()
+        .map(|m| (m.result_index, quantize_score(m.score)))
+        .collect();
+    result_indices.sort_unstable_by_key(|(result_index, _)| *result_index);
+    let mut previous = 0;
+    for (result_index, score) in result_indices {
+        write_varint(out, result_index - previous);
+        out.push(score);
+        previous = result_index;
+    }
+}
+
+/// A decoder for the matches of a word.
+#[derive(Clone)]
+pub struct Postings<'a> {
+    bytes: &'a [u8],
+    page_frequency: u32,
+    remaining: usize,
+    previous: u32,
+}
+
+impl<'a> Postings<'a> {
+    /// Starts decoding `count` matches at the start of `bytes`, which must be valid.
+    pub(crate) fn new(bytes: &'a [u8], count: usize) -> Self {
+        let (page_frequency, len) = read_varint(bytes).unwrap_or((0, 0));
+        Self {
+            bytes: &bytes[len..],
+            page_frequency,
+            remaining: count,
+            previous: 0,
+        }
+    }
+
+    /// Checks that `count` matches can be decoded from the start of `bytes`, and that they refer
+    /// to one of the first `result_count` results.
+    pub(crate) fn is_valid(bytes: &'a [u8], count: usize, result_count: usize) -> bool {
+        if read_varint(bytes).is_none() {
+            return false;
+        }
+        let mut postings = Self::new(bytes, count);
+        while postings.remaining > 0 {
+            match postings.decode_next() {
+                Some(m) if (m.result_index as usize) < result_count => {}
+                _ => return false,
+            }
+        }
+        true
+    }
+
+    /// Returns the number of pages that contain the word.
+    pub fn page_frequency(&self) -> u32 {
+        self.page_frequency
+    }
+
+    fn decode_next(&mut self) -> Option<Match> {
+        let (delta, len) = read_varint(self.bytes)?;
+        let score = *self.bytes.get(len)?;
+        self.previous = self.previous.checked_add(delta)?;
+        self.bytes = &self.bytes[(len + 1)..];
+        self.remaining -= 1;
+        Some(Match {
+            result_index: self.previous,
+            score: dequantize_score(score),
+        })
+    }
+}
+
+impl<'a> Iterator for Postings<'a> {
+    type Item = Match;
+
+    fn next(&mut self) -> Option<Match> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.decode_next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a> ExactSizeIterator for Postings<'a> {}
+
+/// Size of the matches of an index with the fixed-size encoding used until format version 2,
+/// where a match took 8 bytes, compared to the current encoding.
+pub struct PostingsSizeReport {
+    pub match_count: usize,
+    pub fixed_size: usize,
+    pub compressed_size: usize,
+}
+
+impl fmt::Display for PostingsSizeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} matches: {} bytes with fixed-size encoding, {} bytes compressed ({:.1}%)",
+            self.match_count,
+            self.fixed_size,
+            self.compressed_size,
+            100. * self.compressed_size as f32 / self.fixed_size.max(1) as f32
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::index::Match;
+
+    use super::{encode_postings, Postings};
+
+    #[test]
+    fn round_trip() {
+        let matches = vec![
+            Match { result_index: 300, score: 1.5 },
+            Match { result_index: 2, score: 1. },
+            Match { result_index: 100000, score: 1.95 },
+        ];
+        let mut bytes = Vec::new();
+        encode_postings(&matches, 2, &mut bytes);
+        // 1 byte for the page frequency, 1 + 2 + 3 bytes for the deltas and 3 bytes for the
+        // scores.
+        assert_eq!(bytes.len(), 10);
+        assert!(Postings::is_valid(&bytes, 3, 100001));
+        assert!(Postings::is_valid(&bytes, 2, 301));
+        assert!(!Postings::is_valid(&bytes, 3, 100000));
+        assert!(!Postings::is_valid(&bytes[..9], 3, 100001));
+        assert!(!Postings::is_valid(&[], 0, 0));
+
+        let postings = Postings::new(&bytes, 3);
+        assert_eq!(postings.page_frequency(), 2);
+        let decoded: Vec<_> = postings.collect();
+        assert_eq!(
+            decoded.iter().map(|m| m.result_index).collect::<Vec<_>>(),
+            vec![2, 300, 100000]
+        );
+        assert!((decoded[0].score - 1.).abs() < 0.005);
+        assert!((decoded[1].score - 1.5).abs() < 0.005);
+        assert!((decoded[2].score - 1.95).abs() < 0.005);
+    }
+}
@@ -0,0 +1,343 @@
+//! Parser for the query language.
+//!
+//! - Words are optional: pages that match more clauses rank higher, so pasting a whole theorem
+//!   title works.
+//! - `"loi faible"` matches lines that contain all the words of the phrase, or a line that
+//!   contains its first words followed by a line that contains the others.
+//! - `-poisson` excludes the pages that contain `poisson` exactly, wherever it is in the query.
+//! - `binomiale OR poisson` (or `OU`) matches either, but a page that matches both doesn't rank
+//!   higher than a page that matches one of them.
+//! - Parentheses group clauses, e.g. `-(loi forte)`.
+//! - `doc:michelson`, `subject:physique` (or `matiere:`) and `chapitre:20` (or `chapter:`) only
+//!   keep the pages of matching documents. Like exclusions, they apply to the whole query, and
+//!   several filters on the same field match any of their values.
+//!
+//! The parser never fails because the query is searched while it is being typed: unclosed quotes
+//! and parentheses extend to the end of the query.
+
+use std::fmt;
+
+/// A document field that can be filtered on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Field {
+    /// Part of the file name of the document.
+    Document,
+    Subject,
+    Chapter,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Query {
+    /// Consecutive words, kept together so that synonyms spanning several words are recognized.
+    Words(String),
+    Phrase(String),
+    Filter(Field, String),
+    Not(Box<Query>),
+    Or(Vec<Query>),
+    Group(Vec<Query>),
+}
+
+impl Query {
+    fn write(&self, f: &mut fmt::Formatter<'_>, top_level: bool) -> fmt::Result {
+        match self {
+            Query::Words(words) => write!(f, "{}", words),
+            Query::Phrase(phrase) => write!(f, "\"{}\"", phrase),
+            Query::Filter(field, value) => {
+                let name = match field {
+                    Field::Document => "doc",
+                    Field::Subject => "subject",
+                    Field::Chapter => "chapter",
+                };
+                write!(f, "{}:{}", name, value)
+            }
+            Query::Not(query) => {
+                write!(f, "-")?;
+                query.write(f, false)
+            }
+            Query::Or(alternatives) => {
+                for (i, alternative) in alternatives.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " OR ")?;
+                    }
+                    alternative.write(f, false)?;
+                }
+                Ok(())
+            }
+            Query::Group(clauses) => {
+                if !top_level {
+                    write!(f, "(")?;
+                }
+                for (i, clause) in clauses.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    clause.write(f, false)?;
+                }
+                if !top_level {
+                    write!(f, ")")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Writes the query in the query language, without parentheses around the outer group.
+impl fmt::Display for Query {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write(f, true)
+    }
+}
+
+/// Recognizes words of the form `field:value`.
+pub(crate) fn parse_filter(word: &str) -> Option<(Field, String)> {
+    let (name, value) = word.split_once(':')?;
+    let field = match deunicode::deunicode(name).to_lowercase().as_str() {
+        "doc" | "document" => Field::Document,
+        "subject" | "matiere" => Field::Subject,
+        "chapter" | "chapitre" => Field::Chapter,
+        _ => return None,
+    };
+    if value.is_empty() {
+        return None;
+    }
+    Some((field, value.to_owned()))
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Phrase(String),
+    Filter(Field, String),
+    Minus,
+    Or,
+    LeftParenthesis,
+    RightParenthesis,
+}
+
+fn tokenize(query: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = query.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '"' => {
+                let phrase: String = chars.by_ref().take_while(|c| *c != '"').collect();
+                tokens.push(Token::Phrase(phrase));
+            }
+            '(' => tokens.push(Token::LeftParenthesis),
+            ')' => tokens.push(Token::RightParenthesis),
+            '-' if chars.peek().map(|c| !c.is_whitespace()).unwrap_or(false) => {
+                tokens.push(Token::Minus)
+            }
+            c => {
+                let mut word = c.to_string();
+                while let Some(c) = chars.peek() {
+                    if c.is_whitespace() || *c == '"' || *c == '(' || *c == ')' {
+                        break;
+                    }
+                    word.push(*c);
+                    chars.next();
+                }
+                if word == "OR" || word == "OU" {
+                    tokens.push(Token::Or);
+                } else if let Some((field, value)) = parse_filter(&word) {
+                    tokens.push(Token::Filter(field, value));
+                } else {
+                    tokens.push(Token::Word(word));
+                }
+            }
+        }
+    }
+    tokens
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    /// Number of parentheses that are open.
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn sequence(&mut self) -> Query {
+        let mut clauses: Vec<Query> = Vec::new();
+        loop {
+            match self.peek() {
+                None => break,
+                Some(Token::RightParenthesis) => {
+                    self.position += 1;
+                    // Ignore unmatched closing parentheses at the top level.
+                    if self.depth > 0 {
+                        break;
+                    }
+                }
+                Some(Token::Or) => {
+                    // An `OR` without a left operand.
+                    self.position += 1;
+                }
+                Some(_) => {
+                    let clause = self.alternatives();
+                    match (clauses.last_mut(), clause) {
+                        (Some(Query::Words(previous)), Query::Words(words)) => {
+                            previous.push(' ');
+                            previous.push_str(&words);
+                        }
+                        (_, clause) => clauses.push(clause),
+                    }
+                }
+            }
+        }
+        Query::Group(clauses)
+    }
+
+    fn alternatives(&mut self) -> Query {
+        let mut alternatives = vec![self.unary()];
+        while self.peek() == Some(&Token::Or) {
+            self.position += 1;
+            match self.peek() {
+                None | Some(Token::RightParenthesis) | Some(Token::Or) => break,
+                Some(_) => alternatives.push(self.unary()),
+            }
+        }
+        if alternatives.len() == 1 {
+            alternatives.pop().unwrap()
+        } else {
+            Query::Or(alternatives)
+        }
+    }
+
+    fn unary(&mut self) -> Query {
+        if self.peek() == Some(&Token::Minus) {
+            self.position += 1;
+            return match self.peek() {
+                Some(
+                    Token::Word(_)
+                    | Token::Phrase(_)
+                    | Token::Filter(_, _)
+                    | Token::LeftParenthesis,
+                ) => {
+                    Query::Not(Box::new(self.primary()))
+                }
+                // A `-` without an operand.
+                _ => Query::Group(Vec::new()),
+            };
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Query {
+        let token = self.tokens.get(self.position);
+        self.position += 1;
+        match token {
+            Some(Token::Word(w)) => Query::Words(w.clone()),
+            Some(Token::Phrase(p)) => Query::Phrase(p.clone()),
+            Some(Token::Filter(field, value)) => Query::Filter(*field, value.clone()),
+            Some(Token::LeftParenthesis) => {
+                self.depth += 1;
+                let group = self.sequence();
+                self.depth -= 1;
+                group
+            }
+            _ => Query::Group(Vec::new()),
+        }
+    }
+}
+
+pub fn parse(query: &str) -> Query {
+    let mut parser = Parser {
+        tokens: tokenize(query),
+        position: 0,
+        depth: 0,
+    };
+    parser.sequence()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, Field, Query};
+
+    fn words(w: &str) -> Query {
+        Query::Words(w.to_owned())
+    }
+
+    #[test]
+    fn plain_words() {
+        assert_eq!(
+            parse("Inégalités de Bienaymé-Tchebychev."),
+            Query::Group(vec![words("Inégalités de Bienaymé-Tchebychev.")])
+        );
+    }
+
+    #[test]
+    fn operators() {
+        assert_eq!(
+            parse("\"loi faible\" grands -poisson"),
+            Query::Group(vec![
+                Query::Phrase("loi faible".to_owned()),
+                words("grands"),
+                Query::Not(Box::new(words("poisson"))),
+            ])
+        );
+        assert_eq!(
+            parse("loi binomiale OR poisson nombres"),
+            Query::Group(vec![
+                words("loi"),
+                Query::Or(vec![words("binomiale"), words("poisson")]),
+                words("nombres"),
+            ])
+        );
+        assert_eq!(
+            parse("-(loi forte) OR \"loi"),
+            Query::Group(vec![Query::Or(vec![
+                Query::Not(Box::new(Query::Group(vec![words("loi forte")]))),
+                Query::Phrase("loi".to_owned()),
+            ])])
+        );
+    }
+
+    #[test]
+    fn filters() {
+        assert_eq!(
+            parse("interférences doc:michelson -Matière:maths x:y"),
+            Query::Group(vec![
+                words("interférences"),
+                Query::Filter(Field::Document, "michelson".to_owned()),
+                Query::Not(Box::new(Query::Filter(Field::Subject, "maths".to_owned()))),
+                words("x:y"),
+            ])
+        );
+    }
+
+    #[test]
+    fn display() {
+        for query in [
+            "\"loi faible\" grands -poisson",
+            "-(loi forte) OR \"loi\" (binomiale OR poisson)",
+            "interférences doc:michelson -subject:maths x:y",
+        ] {
+            assert_eq!(parse(query).to_string(), query);
+        }
+        assert_eq!(parse("loi  OU  \"faible").to_string(), "loi OR \"faible\"");
+    }
+
+    #[test]
+    fn malformed() {
+        assert_eq!(
+            parse(") OR (loi"),
+            Query::Group(vec![Query::Group(vec![words("loi")])])
+        );
+        assert_eq!(parse("loi -"), Query::Group(vec![words("loi -")]));
+        assert_eq!(
+            parse("(loi -) x"),
+            Query::Group(vec![
+                Query::Group(vec![words("loi"), Query::Group(Vec::new())]),
+                words("x"),
+            ])
+        );
+    }
+}
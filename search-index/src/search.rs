@@ -1,6 +1,18 @@
-use std::collections::{BTreeMap, HashMap};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    ops::Range,
+    sync::Arc,
+};
 
-use crate::{index::SearchIndex, normalize::normalize_and_extract_words};
+use crate::{
+    fuzzy::{max_edit_distance, prefix_edit_distance, EDIT_PENALTY},
+    index::{Match, SearchResult},
+    mapped::{DocumentRef, MappedSearchIndex},
+    normalize::{normalize, tokenize, Language, NormalizerConfig},
+    postings::Postings,
+    query::{parse, Field, Query},
+    synonyms::Synonyms,
+};
 
 /// A rectangle containing words from the query.
 pub struct Highlight {
@@ -8,6 +20,22 @@ pub struct Highlight {
     pub y: i16,
     pub width: u16,
     pub height: u16,
+    /// The normalized query words that the line matches.
+    pub terms: Vec<String>,
+}
+
+/// The number of lines of a page that match a query word.
+pub struct TermHits {
+    /// The normalized query word.
+    pub term: String,
+    pub count: usize,
+}
+
+/// The text of a highlighted line.
+pub struct Snippet {
+    pub text: String,
+    /// Byte ranges of the matched words in `text`.
+    pub highlights: Vec<Range<usize>>,
 }
 
 /// A document or image digest.
@@ -24,52 +52,222 @@ pub struct MatchPage {
     pub width: u16,
     pub height: u16,
     pub highlights: Vec<Highlight>,
+    /// The number of matching lines for each word of the query, in the order of the query,
+    /// including the words that are not on the page. Lines beyond the highlight limit count.
+    pub term_hits: Vec<TermHits>,
+    /// The text of the highlighted lines in reading order, if the index has it.
+    pub snippets: Vec<Snippet>,
+    /// The titles of the outline entries that contain the best match, outermost first.
+    pub breadcrumb: Vec<String>,
+    /// The region of the page that matches best, `None` if the page has no size.
+    pub hotspot: Option<Hotspot>,
+    /// Set if `SearchOptions::explain` is.
+    pub explanation: Option<PageExplanation>,
+}
+
+/// The bounding box of the lines that contribute to the best tile of a page, which is the score
+/// of the page.
+pub struct Hotspot {
+    pub x: i16,
+    pub y: i16,
+    pub width: u16,
+    pub height: u16,
+    pub score: f32,
+}
+
+/// Why a page got its score. The score of a page is the score of its best tile, which is the sum
+/// of the square roots of the contributions of the query words to the tile.
+pub struct PageExplanation {
+    pub score: f32,
+    /// Position of the best tile.
+    pub tile_x: u32,
+    pub tile_y: u32,
+    pub tile_size: u32,
+    pub words: Vec<WordExplanation>,
+}
+
+/// How the best match of a query word contributes to the best tile of a page.
+pub struct WordExplanation {
+    /// The query word, or the whole clause for words within alternatives, which don't add up.
+    pub key: String,
+    /// The indexed word that matched.
+    pub word: String,
+    pub line: Highlight,
+    /// Between 0 and 1, from the font size of the line and whether it starts with a word such as
+    /// "théorème".
+    pub importance: f32,
+    /// Penalty for matching a prefix of the indexed word and for typos.
+    pub match_multiplier: f32,
+    /// Weight of the indexed word given by the scoring, 1 for raw scoring.
+    pub weight: f32,
+    /// Attenuation with the distance between the line and the center of the tile.
+    pub distance_factor: f32,
+    /// Score of the word in the tile, whose square root is added to the score of the tile.
+    pub score: f32,
+}
+
+/// How the matches of the query words are weighed against each other.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Scoring {
+    /// The score of a match only depends on the line that it is on.
+    #[default]
+    Raw,
+    /// The raw score is weighted with BM25.
+    Bm25,
+}
+
+impl Scoring {
+    /// Recognizes "raw" or "bm25".
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "raw" => Some(Self::Raw),
+            "bm25" => Some(Self::Bm25),
+            _ => None,
+        }
+    }
+}
+
+/// Controls which of the matching pages are returned by `search_with_options`.
+#[derive(Clone, Debug)]
+pub struct SearchOptions {
+    /// Number of best pages to skip.
+    pub offset: usize,
+    /// Maximum number of pages to return.
+    pub limit: usize,
+    /// Maximum number of highlighted lines per page.
+    pub max_highlights: usize,
+    pub scoring: Scoring,
+    /// Whether to explain the score of the pages, see `MatchPage::explanation`.
+    pub explain: bool,
+    /// Size of the square tiles that pages are scored on.
+    pub tile_size: u32,
+    /// Distance from a line beyond which its words barely count in the score of a tile.
+    pub hotspot_radius: f32,
+    /// Must be the synonyms that the index was built with, unless `expand_synonyms` is set.
+    pub synonyms: Arc<Synonyms>,
+    /// Also search the synonyms of the query words, for indexes built with `QUERY_SYNONYMS`.
+    /// Synonyms score a bit less than the words as typed, and phrases are searched as typed.
+    pub expand_synonyms: bool,
+    /// The language that the query is normalized with. The same normalization is used for all
+    /// documents, so words of documents in other languages may not match.
+    pub language: Language,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self {
+            offset: 0,
+            limit: 5,
+            max_highlights: 50,
+            scoring: Scoring::default(),
+            explain: false,
+            tile_size: 64,
+            hotspot_radius: 100.,
+            synonyms: Synonyms::builtin(),
+            expand_synonyms: false,
+            language: Language::default(),
+        }
+    }
+}
+
+impl SearchOptions {
+    /// Returns the synonyms that the words of the index were normalized with.
+    fn index_synonyms(&self) -> &Synonyms {
+        if self.expand_synonyms {
+            Synonyms::none()
+        } else {
+            &self.synonyms
+        }
+    }
 }
 
-const TILE_SIZE: u32 = 64;
-const HOTSPOT_RADIUS: f32 = 100.;
+/// The pages returned by `search_with_options`.
+pub struct SearchResults {
+    pub pages: Vec<MatchPage>,
+    /// Number of pages that match the query, including the ones that were not returned.
+    pub total: usize,
+}
 
 #[derive(Clone, Default)]
 struct PageHotspotTile {
-    max_score_per_word: HashMap<String, f32>,
+    /// The best score of each key and the index of the hit that it comes from.
+    max_score_per_word: HashMap<String, (f32, usize)>,
 }
 
 impl PageHotspotTile {
     fn total_score(&self) -> f32 {
-        self.max_score_per_word.values().map(|s| s.sqrt()).sum()
+        self.max_score_per_word.values().map(|(s, _)| s.sqrt()).sum()
     }
 }
 
+/// The tiles of a page, row by row. Words only add up within a tile, so that words in different
+/// columns don't count as close to each other.
 struct PageHotspotImage {
+    tile_size: u32,
+    radius: f32,
+    tiled_width: u32,
     tiled_height: u32,
     tiles: Vec<PageHotspotTile>,
 }
 
 impl PageHotspotImage {
-    fn new(height: u32) -> Self {
-        let tiled_height = (height + TILE_SIZE - 1) / TILE_SIZE;
+    fn new(width: u32, height: u32, options: &SearchOptions) -> Self {
+        let tile_size = options.tile_size.max(1);
+        let tiled_width = width.div_ceil(tile_size);
+        let tiled_height = height.div_ceil(tile_size);
         Self {
+            tile_size,
+            radius: options.hotspot_radius.max(1.),
+            tiled_width,
             tiled_height,
-            tiles: vec![Default::default(); tiled_height as usize],
+            tiles: vec![Default::default(); (tiled_width * tiled_height) as usize],
         }
     }
 
-    fn update_score(&mut self, y: f32, word: String, score: f32) {
-        let tile_y_min = (y - HOTSPOT_RADIUS) as i32 / TILE_SIZE as i32;
-        let tile_y_max = (y + HOTSPOT_RADIUS + TILE_SIZE as f32 - 1.) as i32 / TILE_SIZE as i32;
+    /// Returns the range of tiles along an axis that are within the radius of a segment.
+    fn tile_range(&self, start: f32, end: f32, tile_count: u32) -> (i32, i32) {
+        let tile_size = self.tile_size as f32;
+        let min = ((start - self.radius) / tile_size).floor() as i32;
+        let max = ((end + self.radius) / tile_size).floor() as i32;
+        (min.max(0), max.min(tile_count as i32 - 1))
+    }
+
+    fn update_score(
+        &mut self,
+        line: &SearchResult,
+        word: &str,
+        score: f32,
+        hit_index: usize,
+    ) {
+        let tile_size = self.tile_size as f32;
+        let x_start = line.x as f32;
+        let x_end = x_start + line.width as f32;
+        let y = line.y as f32 + line.height as f32 / 2.;
+        let (tile_x_min, tile_x_max) = self.tile_range(x_start, x_end, self.tiled_width);
+        let (tile_y_min, tile_y_max) = self.tile_range(y, y, self.tiled_height);
         for ty in tile_y_min..=tile_y_max {
-            if ty < 0 || ty >= self.tiled_height as i32 {
-                continue;
+            for tx in tile_x_min..=tile_x_max {
+                let center_x = (tx as f32 + 0.5) * tile_size;
+                let center_y = (ty as f32 + 0.5) * tile_size;
+                // Lines are much wider than tall, so measure the distance to the closest point of
+                // the line horizontally and to its middle vertically.
+                let dx = (center_x - center_x.clamp(x_start, x_end)).abs();
+                let dy = (center_y - y).abs();
+                let distance = (dx * dx + dy * dy).sqrt();
+                // Clamp the distance so that we always get the maximum score on the tile that
+                // we're in.
+                let distance = distance.max(tile_size / 2.);
+                let factor = (self.radius - distance).max(0.) / self.radius;
+                let factor = (factor * factor).max(0.25);
+                let tile = &mut self.tiles[(ty as u32 * self.tiled_width + tx as u32) as usize];
+                let max_score = tile
+                    .max_score_per_word
+                    .entry(word.to_owned())
+                    .or_insert((0., hit_index));
+                if score * factor > max_score.0 {
+                    *max_score = (score * factor, hit_index);
+                }
             }
-            let distance = (y - (ty as f32 + 0.5) * TILE_SIZE as f32).abs();
-            // Clamp the distance so that we always get the maximum score on the tile that we're
-            // in.
-            let distance = distance.max(TILE_SIZE as f32 / 2.);
-            let factor = (HOTSPOT_RADIUS - distance).max(0.) / HOTSPOT_RADIUS;
-            let factor = (factor * factor).max(0.25);
-            let tile = &mut self.tiles[ty as usize];
-            let max_score = tile.max_score_per_word.entry(word.clone()).or_default();
-            *max_score = max_score.max(score * factor);
         }
     }
 
@@ -79,72 +277,1256 @@ impl PageHotspotImage {
             .max_by(|x, y| x.partial_cmp(y).unwrap())
             .unwrap_or(0.)
     }
+
+    /// Returns the index of the tile with the maximum score, if there are tiles.
+    fn best_tile(&self) -> Option<(usize, &PageHotspotTile)> {
+        self.tiles
+            .iter()
+            .enumerate()
+            .max_by(|(_, x), (_, y)| x.total_score().partial_cmp(&y.total_score()).unwrap())
+    }
+
+    /// Returns the line of the best scoring hit of the best tile.
+    fn best_line(&self, hits: &[Hit]) -> Option<u32> {
+        let (_, tile) = self.best_tile()?;
+        tile.max_score_per_word
+            .values()
+            .max_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap())
+            .map(|(_, hit_index)| hits[*hit_index].result_index)
+    }
+
+    fn hotspot(&self, search_index: &MappedSearchIndex, hits: &[Hit]) -> Option<Hotspot> {
+        let (tile_index, tile) = self.best_tile()?;
+        let tile_x = (tile_index as u32 % self.tiled_width * self.tile_size) as i32;
+        let tile_y = (tile_index as u32 / self.tiled_width * self.tile_size) as i32;
+        let lines = tile.max_score_per_word.values().map(|(_, hit_index)| {
+            let line = search_index.result(hits[*hit_index].result_index as usize);
+            (
+                line.x as i32,
+                line.y as i32,
+                line.x as i32 + line.width as i32,
+                line.y as i32 + line.height as i32,
+            )
+        });
+        // Fall back to the tile itself if no line contributes to it, which happens when the
+        // matching lines are outside of the page.
+        let (x_min, y_min, x_max, y_max) = lines
+            .reduce(|a, b| (a.0.min(b.0), a.1.min(b.1), a.2.max(b.2), a.3.max(b.3)))
+            .unwrap_or((
+                tile_x,
+                tile_y,
+                tile_x + self.tile_size as i32,
+                tile_y + self.tile_size as i32,
+            ));
+        Some(Hotspot {
+            x: x_min as i16,
+            y: y_min as i16,
+            width: (x_max - x_min) as u16,
+            height: (y_max - y_min) as u16,
+            score: tile.total_score(),
+        })
+    }
+
+    fn explain(&self, search_index: &MappedSearchIndex, hits: &[Hit]) -> PageExplanation {
+        let (tile_index, tile) = match self.best_tile() {
+            Some(t) => t,
+            // The page has no height, so no tiles.
+            None => {
+                return PageExplanation {
+                    score: 0.,
+                    tile_x: 0,
+                    tile_y: 0,
+                    tile_size: self.tile_size,
+                    words: Vec::new(),
+                }
+            }
+        };
+        let mut words: Vec<_> = tile
+            .max_score_per_word
+            .iter()
+            .map(|(key, (score, hit_index))| {
+                let hit = &hits[*hit_index];
+                let details = hit.details.as_ref().unwrap();
+                let result = search_index.result(hit.result_index as usize);
+                WordExplanation {
+                    key: key.clone(),
+                    word: hit.word.clone(),
+                    line: Highlight {
+                        x: result.x,
+                        y: result.y,
+                        width: result.width,
+                        height: result.height,
+                        terms: vec![hit.term.clone()],
+                    },
+                    importance: details.line_score - 1.,
+                    match_multiplier: details.match_multiplier,
+                    weight: details.weight,
+                    distance_factor: if hit.score > 0. { score / hit.score } else { 0. },
+                    score: *score,
+                }
+            })
+            .collect();
+        words.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
+        PageExplanation {
+            score: tile.total_score(),
+            tile_x: tile_index as u32 % self.tiled_width * self.tile_size,
+            tile_y: tile_index as u32 / self.tiled_width * self.tile_size,
+            tile_size: self.tile_size,
+            words,
+        }
+    }
 }
 
-pub fn search(search_index: &SearchIndex, query: &str) -> Vec<MatchPage> {
-    let words = normalize_and_extract_words(&query);
-    if words.is_empty() {
-        return Vec::new();
+/// A line that matches a clause of the query.
+struct Hit {
+    result_index: u32,
+    /// On a given tile, only the best scoring hit for each key counts.
+    key: String,
+    /// The normalized query word that matched.
+    term: String,
+    /// The indexed word that matched.
+    word: String,
+    score: f32,
+    /// How the score was computed, only kept when explaining the results.
+    details: Option<Box<HitDetails>>,
+}
+
+struct HitDetails {
+    /// Score stored in the index for the line, one plus its importance.
+    line_score: f32,
+    match_multiplier: f32,
+    weight: f32,
+}
+
+/// Multiplies the score of the matches of synonyms of the query words.
+const SYNONYM_PENALTY: f32 = 0.9;
+
+// Usual values of the BM25 parameters.
+const BM25_K1: f32 = 1.2;
+const BM25_B: f32 = 0.75;
+
+/// Returns the inverse page frequency of a word that is on `page_frequency` pages.
+fn idf(search_index: &MappedSearchIndex, page_frequency: u32) -> f32 {
+    let n = search_index.page_count() as f32;
+    let df = page_frequency as f32;
+    (1. + (n - df + 0.5) / (df + 0.5)).ln()
+}
+
+/// Returns the factors that BM25 applies to the matches of a word on each page.
+fn bm25_weights(
+    search_index: &MappedSearchIndex,
+    matches: &[Match],
+    idf: f32,
+) -> HashMap<u32, f32> {
+    let mut term_frequencies: HashMap<u32, u32> = HashMap::new();
+    for m in matches {
+        let page_index = search_index.result(m.result_index as usize).page_index;
+        *term_frequencies.entry(page_index).or_default() += 1;
+    }
+    let average_length = search_index.average_page_length().max(1.);
+    term_frequencies
+        .into_iter()
+        .map(|(page_index, tf)| {
+            let tf = tf as f32;
+            let length = search_index.page(page_index as usize).length as f32;
+            let norm = BM25_K1 * (1. - BM25_B + BM25_B * length / average_length);
+            (page_index, idf * tf * (BM25_K1 + 1.) / (tf + norm))
+        })
+        .collect()
+}
+
+/// Finds the lines containing an indexed word that starts with `w`, tolerating typos in long
+/// enough words.
+fn word_hits(
+    search_index: &MappedSearchIndex,
+    options: &SearchOptions,
+    w: &str,
+    key: &str,
+    hits: &mut Vec<Hit>,
+) {
+    let max_distance = max_edit_distance(w);
+    let fuzzy_words = if max_distance > 0 {
+        search_index.words_with_prefix_within_distance(w, max_distance)
+    } else {
+        None
+    };
+    let candidates: Box<dyn Iterator<Item = (String, Postings)>> = match fuzzy_words {
+        Some(words) => Box::new(words),
+        None => Box::new(search_index.words_with_prefix(w)),
+    };
+    for (word, matches) in candidates {
+        let distance = if max_distance > 0 { prefix_edit_distance(w, &word) } else { 0 };
+        let score_multiplier =
+            (w.len() as f32) / (word.len() as f32) * EDIT_PENALTY.powi(distance as i32);
+        let idf = idf(search_index, matches.page_frequency());
+        let matches: Vec<Match> = matches.collect();
+        let weights = match options.scoring {
+            Scoring::Raw => HashMap::new(),
+            Scoring::Bm25 => bm25_weights(search_index, &matches, idf),
+        };
+        for m in matches {
+            let weight = match options.scoring {
+                Scoring::Raw => 1.,
+                Scoring::Bm25 => {
+                    weights[&search_index.result(m.result_index as usize).page_index]
+                }
+            };
+            let details = options.explain.then(|| {
+                Box::new(HitDetails {
+                    line_score: m.score,
+                    match_multiplier: score_multiplier,
+                    weight,
+                })
+            });
+            hits.push(Hit {
+                result_index: m.result_index,
+                key: key.to_owned(),
+                term: w.to_owned(),
+                word: word.clone(),
+                score: m.score * score_multiplier * weight,
+                details,
+            });
+        }
+    }
+}
+
+/// Returns whether `words` follow each other in a line, starting at `start` if given.
+fn consecutive(
+    search_index: &MappedSearchIndex,
+    result_index: u32,
+    words: &[String],
+    start: Option<u16>,
+) -> bool {
+    let positions: Vec<Vec<u16>> = words
+        .iter()
+        .map(|w| search_index.word_positions(result_index, w).collect())
+        .collect();
+    let first_positions = match positions.first() {
+        Some(p) => p,
+        None => return true,
+    };
+    first_positions
+        .iter()
+        .filter(|p| start.is_none_or(|s| **p == s))
+        .any(|p| {
+            positions
+                .iter()
+                .enumerate()
+                .all(|(i, word_positions)| word_positions.contains(&(p + i as u16)))
+        })
+}
+
+/// Finds the lines that contain the words of a phrase in a row, or that contain its first words
+/// in a row and are followed on the same page by a line that starts with the rest. Indexes
+/// without word positions only check that the lines contain the words.
+fn phrase_hits(
+    search_index: &MappedSearchIndex,
+    options: &SearchOptions,
+    words: &[String],
+    key: Option<&str>,
+    hits: &mut Vec<Hit>,
+) {
+    let mut lines_per_word: Vec<HashMap<u32, f32>> = Vec::with_capacity(words.len());
+    let mut weights = Vec::with_capacity(words.len());
+    for w in words {
+        match search_index.postings(w) {
+            Some(matches) => {
+                weights.push(match options.scoring {
+                    Scoring::Raw => 1.,
+                    Scoring::Bm25 => idf(search_index, matches.page_frequency()),
+                });
+                lines_per_word.push(matches.map(|m| (m.result_index, m.score)).collect());
+            }
+            None => return,
+        }
+    }
+    let first_lines = match lines_per_word.first() {
+        Some(lines) => lines,
+        None => return,
+    };
+
+    for &r in first_lines.keys() {
+        let in_line = lines_per_word
+            .iter()
+            .take_while(|lines| lines.contains_key(&r))
+            .count();
+        let next = r + 1;
+        let next_on_page = (next as usize) < search_index.result_count()
+            && search_index.result(next as usize).page_index
+                == search_index.result(r as usize).page_index;
+        let continues = |split: usize| {
+            split == words.len()
+                || (next_on_page
+                    && lines_per_word[split..].iter().all(|lines| lines.contains_key(&next)))
+        };
+        let split = if search_index.has_word_positions() {
+            // Prefer the phrase within a single line.
+            (1..=in_line).rev().find(|&split| {
+                consecutive(search_index, r, &words[..split], None)
+                    && continues(split)
+                    && (split == words.len()
+                        || consecutive(search_index, next, &words[split..], Some(0)))
+            })
+        } else {
+            Some(in_line).filter(|&split| continues(split))
+        };
+        let split = match split {
+            Some(split) => split,
+            None => continue,
+        };
+        for (i, (w, lines)) in words.iter().zip(lines_per_word.iter()).enumerate() {
+            let result_index = if i < split { r } else { next };
+            let details = options.explain.then(|| {
+                Box::new(HitDetails {
+                    line_score: lines[&result_index],
+                    match_multiplier: 1.,
+                    weight: weights[i],
+                })
+            });
+            hits.push(Hit {
+                result_index,
+                key: key.unwrap_or(w).to_owned(),
+                term: w.clone(),
+                word: w.clone(),
+                score: lines[&result_index] * weights[i],
+                details,
+            });
+        }
     }
+}
 
-    let mut pages: BTreeMap<u32, (Vec<u32>, PageHotspotImage)> = BTreeMap::new();
-    for w in words.into_iter() {
-        // Prefix key search.
-        for (word, matches) in search_index.words.range(w.clone()..) {
-            if !word.starts_with(&w) {
-                break;
+/// Finds the lines that contain a synonym of consecutive query words, which count as matches of
+/// each of these words.
+fn synonym_hits(
+    search_index: &MappedSearchIndex,
+    options: &SearchOptions,
+    words: &[String],
+    key: Option<&str>,
+    hits: &mut Vec<Hit>,
+) {
+    for (range, group) in options.synonyms.find(options.language, words, true) {
+        let matched = &words[range];
+        for alternative in options.synonyms.group_words(options.language, group) {
+            if alternative == matched {
+                continue;
+            }
+            let mut alternative_hits = Vec::new();
+            phrase_hits(search_index, options, &alternative, None, &mut alternative_hits);
+            for hit in alternative_hits {
+                for w in matched {
+                    let details = hit.details.as_ref().map(|d| {
+                        Box::new(HitDetails {
+                            line_score: d.line_score,
+                            match_multiplier: d.match_multiplier * SYNONYM_PENALTY,
+                            weight: d.weight,
+                        })
+                    });
+                    hits.push(Hit {
+                        result_index: hit.result_index,
+                        key: key.unwrap_or(w).to_owned(),
+                        term: w.clone(),
+                        word: hit.word.clone(),
+                        score: hit.score * SYNONYM_PENALTY,
+                        details,
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// Constraints on the pages that can be returned, collected from the whole query.
+#[derive(Default)]
+struct PageFilter {
+    excluded_pages: HashSet<u32>,
+    /// A document must match at least one of the values given for each field.
+    required: Vec<(Field, String)>,
+    /// A document must not be allowed by any of these, which come from excluded filters.
+    excluded: Vec<PageFilter>,
+}
+
+impl PageFilter {
+    fn document_matches(document: &DocumentRef, field: Field, value: &str) -> bool {
+        let normalize = |s: &str| deunicode::deunicode(s).to_lowercase();
+        match field {
+            Field::Document => normalize(document.name).contains(&normalize(value)),
+            Field::Subject => normalize(document.subject) == normalize(value),
+            Field::Chapter => match value.parse::<u16>() {
+                Ok(chapter) => document.chapter == Some(chapter),
+                Err(_) => false,
+            },
+        }
+    }
+
+    /// Whether the filter only allows some documents.
+    fn constrains_documents(&self) -> bool {
+        !self.required.is_empty() || !self.excluded.is_empty()
+    }
+
+    fn allows(&self, document: &DocumentRef) -> bool {
+        let fields: HashSet<Field> = self.required.iter().map(|(f, _)| *f).collect();
+        fields.into_iter().all(|field| {
+            self.required
+                .iter()
+                .filter(|(f, _)| *f == field)
+                .any(|(_, value)| Self::document_matches(document, field, value))
+        }) && !self.excluded.iter().any(|f| f.allows(document))
+    }
+
+    /// Returns whether each document of the index can be returned.
+    fn allowed_documents(&self, search_index: &MappedSearchIndex) -> Vec<bool> {
+        (0..search_index.document_count())
+            .map(|i| self.allows(&search_index.document(i)))
+            .collect()
+    }
+}
+
+/// Collects the pages that contain the words of an excluded clause, without prefixes or typos,
+/// and the filters of the clause. Returns whether the clause has words.
+fn excluded_matches(
+    search_index: &MappedSearchIndex,
+    options: &SearchOptions,
+    query: &Query,
+    pages: &mut HashSet<u32>,
+    filter: &mut PageFilter,
+) -> bool {
+    let page_index = |result_index: u32| search_index.result(result_index as usize).page_index;
+    match query {
+        Query::Words(text) => {
+            let words = normalize(text, options.language.profile(), options.index_synonyms());
+            for w in words.iter() {
+                let matches = search_index.postings(w).into_iter().flatten();
+                pages.extend(matches.map(|m| page_index(m.result_index)));
+            }
+            !words.is_empty()
+        }
+        Query::Phrase(text) => {
+            let words = normalize(text, options.language.profile(), options.index_synonyms());
+            let mut hits = Vec::new();
+            phrase_hits(search_index, options, &words, None, &mut hits);
+            pages.extend(hits.iter().map(|h| page_index(h.result_index)));
+            !words.is_empty()
+        }
+        Query::Filter(field, value) => {
+            filter.required.push((*field, value.clone()));
+            false
+        }
+        Query::Not(clause) => {
+            exclude(search_index, options, clause, filter);
+            false
+        }
+        Query::Or(clauses) | Query::Group(clauses) => {
+            let mut has_words = false;
+            for c in clauses {
+                has_words |= excluded_matches(search_index, options, c, pages, filter);
+            }
+            has_words
+        }
+    }
+}
+
+/// Excludes the pages that match a clause. The filters of the clause restrict which pages of its
+/// words are excluded, or are excluded together if it has no words.
+fn exclude(
+    search_index: &MappedSearchIndex,
+    options: &SearchOptions,
+    clause: &Query,
+    filter: &mut PageFilter,
+) {
+    let mut pages = HashSet::new();
+    let mut negated_filter = PageFilter::default();
+    if excluded_matches(search_index, options, clause, &mut pages, &mut negated_filter) {
+        let allowed_documents = negated_filter.allowed_documents(search_index);
+        filter.excluded_pages.extend(pages.into_iter().filter(|p| {
+            !negated_filter.excluded_pages.contains(p)
+                && allowed_documents[search_index.page(*p as usize).document_index as usize]
+        }));
+    } else if negated_filter.constrains_documents() {
+        filter.excluded.push(negated_filter);
+    }
+}
+
+/// Collects the words and the hits of the positive clauses of a query and the constraints that
+/// its filters and negative clauses put on the pages.
+fn evaluate(
+    search_index: &MappedSearchIndex,
+    options: &SearchOptions,
+    query: &Query,
+    key: Option<&str>,
+    terms: &mut Vec<String>,
+    hits: &mut Vec<Hit>,
+    filter: &mut PageFilter,
+) {
+    let mut add_terms = |words: &[String]| {
+        for w in words {
+            if !terms.contains(w) {
+                terms.push(w.clone());
+            }
+        }
+    };
+    match query {
+        Query::Words(text) => {
+            let words = normalize(text, options.language.profile(), options.index_synonyms());
+            add_terms(&words);
+            for w in words.iter() {
+                word_hits(search_index, options, w, key.unwrap_or(w), hits);
+            }
+            if options.expand_synonyms {
+                synonym_hits(search_index, options, &words, key, hits);
             }
-            let score_multiplier = (w.len() as f32) / (word.len() as f32);
-            for m in matches {
-                let result = &search_index.results[m.result_index as usize];
-                let page = &search_index.pages[result.page_index as usize];
-                let (results, hotspot_image) = pages.entry(result.page_index)
-                    .or_insert_with(|| (Vec::new(), PageHotspotImage::new(page.height.into())));
-                // Limit the amount of rect per page.
-                if results.len() < 50 && !results.contains(&m.result_index) {
-                    results.push(m.result_index);
+        }
+        Query::Phrase(text) => {
+            let words = normalize(text, options.language.profile(), options.index_synonyms());
+            add_terms(&words);
+            phrase_hits(search_index, options, &words, key, hits);
+        }
+        Query::Filter(field, value) => filter.required.push((*field, value.clone())),
+        Query::Not(clause) => exclude(search_index, options, clause, filter),
+        Query::Or(alternatives) => {
+            // Give the same key to all alternatives so that they don't add up.
+            let key = key.map(|k| k.to_owned()).unwrap_or_else(|| query.to_string());
+            for a in alternatives {
+                evaluate(search_index, options, a, Some(&key), terms, hits, filter);
+            }
+        }
+        Query::Group(clauses) => {
+            for c in clauses {
+                evaluate(search_index, options, c, key, terms, hits, filter);
+            }
+        }
+    }
+}
+
+/// Returns the best pages for `query` with the default options.
+pub fn search(search_index: &MappedSearchIndex, query: &str) -> Vec<MatchPage> {
+    search_with_options(search_index, query, &SearchOptions::default()).pages
+}
+
+/// Returns highlights around the matched words of a line if the index has boxes for all of them,
+/// and around the whole line otherwise.
+fn line_highlights(
+    search_index: &MappedSearchIndex,
+    result_index: u32,
+    hits: &[&Hit],
+) -> Vec<Highlight> {
+    let line = search_index.result(result_index as usize);
+    let mut highlights: Vec<Highlight> = Vec::new();
+    let mut terms: Vec<String> = Vec::new();
+    let mut all_boxed = true;
+    for hit in hits {
+        if !terms.contains(&hit.term) {
+            terms.push(hit.term.clone());
+        }
+        let mut boxed = false;
+        for (x, width) in search_index.word_boxes(result_index, &hit.word) {
+            boxed = true;
+            match highlights.iter_mut().find(|h| h.x == x && h.width == width) {
+                Some(h) => {
+                    if !h.terms.contains(&hit.term) {
+                        h.terms.push(hit.term.clone());
+                    }
                 }
-                let y = result.y as f32 + result.height as f32 / 2.;
-                hotspot_image.update_score(y, w.to_owned(), m.score * score_multiplier);
+                None => highlights.push(Highlight {
+                    x,
+                    y: line.y,
+                    width,
+                    height: line.height,
+                    terms: vec![hit.term.clone()],
+                }),
+            }
+        }
+        all_boxed &= boxed;
+    }
+    if all_boxed {
+        return highlights;
+    }
+    vec![Highlight {
+        x: line.x,
+        y: line.y,
+        width: line.width,
+        height: line.height,
+        terms,
+    }]
+}
+
+/// Returns the byte ranges of the words of `text` that normalize to one of `words`.
+fn matched_ranges(
+    text: &str,
+    words: &[&str],
+    config: &NormalizerConfig,
+    synonyms: &Synonyms,
+) -> Vec<Range<usize>> {
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    for token in tokenize(text, config, synonyms) {
+        // The words of the canonical form of a synonym share their range.
+        if words.contains(&token.word.as_str()) && ranges.last() != Some(&token.offsets) {
+            ranges.push(token.offsets);
+        }
+    }
+    ranges
+}
+
+/// Returns the text of the highlighted lines, sorted by result because the generator adds the
+/// lines of a page in reading order. The lines are normalized like the document was.
+fn snippets(
+    search_index: &MappedSearchIndex,
+    lines: &[(u32, Vec<&Hit>)],
+    language: Language,
+    synonyms: &Synonyms,
+) -> Vec<Snippet> {
+    let mut lines: Vec<_> = lines.iter().collect();
+    lines.sort_by_key(|(r, _)| *r);
+    lines
+        .into_iter()
+        .filter_map(|(r, line_hits)| {
+            let text = search_index.result_text(*r as usize)?;
+            let words: Vec<&str> = line_hits.iter().map(|h| h.word.as_str()).collect();
+            let highlights = matched_ranges(&text, &words, language.profile(), synonyms);
+            Some(Snippet { text, highlights })
+        })
+        .collect()
+}
+
+/// The hits of a query on a page.
+struct PageMatches<'a> {
+    /// The lines to highlight, with their hits.
+    highlights: Vec<(u32, Vec<&'a Hit>)>,
+    /// The lines that match each word.
+    term_lines: HashMap<&'a str, HashSet<u32>>,
+    hotspot_image: PageHotspotImage,
+}
+
+pub fn search_with_options(
+    search_index: &MappedSearchIndex,
+    query: &str,
+    options: &SearchOptions,
+) -> SearchResults {
+    let mut terms = Vec::new();
+    let mut hits = Vec::new();
+    let mut filter = PageFilter::default();
+    evaluate(
+        search_index,
+        options,
+        &parse(query),
+        None,
+        &mut terms,
+        &mut hits,
+        &mut filter,
+    );
+    let allowed_documents = filter.allowed_documents(search_index);
+
+    let mut pages: BTreeMap<u32, PageMatches> = BTreeMap::new();
+    for (hit_index, hit) in hits.iter().enumerate() {
+        let result = search_index.result(hit.result_index as usize);
+        if filter.excluded_pages.contains(&result.page_index) {
+            continue;
+        }
+        let page = search_index.page(result.page_index as usize);
+        if !allowed_documents[page.document_index as usize] {
+            continue;
+        }
+        let matches = pages.entry(result.page_index).or_insert_with(|| PageMatches {
+            highlights: Vec::new(),
+            term_lines: HashMap::new(),
+            hotspot_image: PageHotspotImage::new(page.width.into(), page.height.into(), options),
+        });
+        let highlights = &mut matches.highlights;
+        match highlights.iter().position(|(r, _)| *r == hit.result_index) {
+            Some(i) => highlights[i].1.push(hit),
+            // Limit the amount of rect per page.
+            None if highlights.len() < options.max_highlights => {
+                highlights.push((hit.result_index, vec![hit]));
             }
+            None => {}
         }
+        matches
+            .term_lines
+            .entry(&hit.term)
+            .or_default()
+            .insert(hit.result_index);
+        matches
+            .hotspot_image
+            .update_score(&result, &hit.key, hit.score, hit_index);
     }
 
     let mut pages: Vec<_> = pages.into_iter().collect();
     pages.sort_by(|(_, page_a), (_, page_b)| {
-        let score_a: f32 = page_a.1.maximum_score();
-        let score_b: f32 = page_b.1.maximum_score();
+        let score_a: f32 = page_a.hotspot_image.maximum_score();
+        let score_b: f32 = page_b.hotspot_image.maximum_score();
         score_b.partial_cmp(&score_a).unwrap()
     });
 
-    pages
+    let total = pages.len();
+    let pages = pages
         .into_iter()
-        .map(|(page_index, page_search)| {
-            let (result_indices, _score) = page_search;
-            let highlights = result_indices
-                .into_iter()
+        .skip(options.offset)
+        .take(options.limit)
+        .map(|(page_index, matches)| {
+            let hotspot = matches.hotspot_image.hotspot(search_index, &hits);
+            let explanation = options
+                .explain
+                .then(|| matches.hotspot_image.explain(search_index, &hits));
+            let term_hits = terms
+                .iter()
+                .map(|term| TermHits {
+                    term: term.clone(),
+                    count: matches.term_lines.get(term.as_str()).map_or(0, |lines| lines.len()),
+                })
+                .collect();
+            let page = search_index.page(page_index as usize);
+            let document = search_index.document(page.document_index as usize);
+            let snippets = snippets(
+                search_index,
+                &matches.highlights,
+                document.language,
+                options.index_synonyms(),
+            );
+            let breadcrumb = matches
+                .hotspot_image
+                .best_line(&hits)
+                .or_else(|| matches.highlights.first().map(|(r, _)| *r))
                 .map(|r| {
-                    let result = &search_index.results[r as usize];
-                    Highlight {
-                        x: result.x,
-                        y: result.y,
-                        width: result.width,
-                        height: result.height,
-                    }
+                    search_index
+                        .breadcrumb(r as usize)
+                        .into_iter()
+                        .map(str::to_owned)
+                        .collect()
                 })
+                .unwrap_or_default();
+            let highlights = matches
+                .highlights
+                .into_iter()
+                .flat_map(|(r, line_hits)| line_highlights(search_index, r, &line_hits))
                 .collect();
-            let page = &search_index.pages[page_index as usize];
-            let document_digest = search_index.documents[page.document_index as usize].to_owned();
+            let document_digest = document.name.to_owned();
             MatchPage {
                 document_digest,
                 number: page.page_nr,
-                rendered_avif: page.rendered_avif.clone(),
-                rendered_jpeg: page.rendered_jpeg.clone(),
+                rendered_avif: page.rendered_avif.to_owned(),
+                rendered_jpeg: page.rendered_jpeg.to_owned(),
                 width: page.width,
                 height: page.height,
                 highlights,
+                term_hits,
+                snippets,
+                breadcrumb,
+                hotspot,
+                explanation,
             }
         })
-        .take(5)
-        .collect()
+        .collect();
+    SearchResults { pages, total }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::sync::Arc;
+
+    use crate::{
+        index::{
+            Document, Match, OutlineEntry, Page, SearchIndex, SearchResult, WordBox, WordPosition,
+        },
+        mapped::MappedSearchIndex,
+        normalize::{tokenize, Language, NormalizerConfig},
+        synonyms::Synonyms,
+    };
+
+    use super::{search, search_with_options, Scoring, SearchOptions};
+
+    fn map_index(index: &SearchIndex) -> MappedSearchIndex {
+        let mut bytes = Vec::new();
+        index.serialize(&mut bytes).unwrap();
+        MappedSearchIndex::from_bytes(bytes).unwrap()
+    }
+
+    /// Returns an index with one page per line of text, in one document per subject.
+    fn index_with_subjects(lines: &[(&str, &str)]) -> SearchIndex {
+        index_with_normalization(lines, &Default::default(), &Synonyms::builtin())
+    }
+
+    fn index_with_normalization(
+        lines: &[(&str, &str)],
+        config: &NormalizerConfig,
+        synonyms: &Synonyms,
+    ) -> SearchIndex {
+        let mut index = SearchIndex::new();
+        for (subject, line) in lines.iter() {
+            let document_index = match index.documents.iter().position(|d| d.subject == *subject) {
+                Some(d) => d,
+                None => {
+                    index.documents.push(Document {
+                        name: format!("{}.pdf", subject),
+                        subject: subject.to_string(),
+                        chapter: Some(index.documents.len() as u16 + 1),
+                        language: config.language(),
+                    });
+                    index.documents.len() - 1
+                }
+            };
+            let page_index = push_page(&mut index, document_index);
+            push_line(&mut index, page_index, (0, 100), line, config, synonyms);
+        }
+        index
+    }
+
+    /// Adds a page to a document and returns its index.
+    pub(crate) fn push_page(index: &mut SearchIndex, document_index: usize) -> usize {
+        index.pages.push(Page {
+            document_index: document_index as u16,
+            page_nr: index.pages.len() as u16,
+            rendered_avif: String::new(),
+            rendered_jpeg: String::new(),
+            width: 1000,
+            height: 1000,
+        });
+        index.pages.len() - 1
+    }
+
+    /// Adds a line of text at a position on a page, with its words and their positions.
+    pub(crate) fn push_line(
+        index: &mut SearchIndex,
+        page_index: usize,
+        (x, y): (i16, i16),
+        line: &str,
+        config: &NormalizerConfig,
+        synonyms: &Synonyms,
+    ) {
+        let result_index = index.results.len() as u32;
+        index.results.push(SearchResult {
+            page_index: page_index as u32,
+            x,
+            y,
+            width: 400,
+            height: 20,
+        });
+        index.result_texts.push(line.to_string());
+        let tokens = tokenize(line, config, synonyms);
+        for t in tokens.iter() {
+            index.word_positions.push(WordPosition {
+                result_index,
+                word: t.word.clone(),
+                position: t.position as u16,
+            });
+        }
+        let mut words: Vec<_> = tokens.into_iter().map(|t| t.word).collect();
+        words.sort();
+        words.dedup();
+        for w in words {
+            index.words.entry(w).or_default().push(Match {
+                result_index,
+                score: 1.5,
+            });
+        }
+    }
+
+    fn build_index_with_subjects(lines: &[(&str, &str)]) -> MappedSearchIndex {
+        map_index(&index_with_subjects(lines))
+    }
+
+    pub(crate) fn build_index(lines: &[&str]) -> MappedSearchIndex {
+        let lines: Vec<_> = lines.iter().map(|l| ("maths", *l)).collect();
+        build_index_with_subjects(&lines)
+    }
+
+    #[test]
+    fn typos() {
+        let index = build_index(&["Inégalité de Bienaymé-Tchebychev", "Loi de Poisson"]);
+
+        let results = search(&index, "tchebichev");
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].number, 0);
+
+        let results = search(&index, "poison");
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].number, 1);
+
+        // Short words must match exactly.
+        assert!(search(&index, "lai").is_empty());
+    }
+
+    #[test]
+    fn query_language() {
+        let index = build_index(&[
+            "Loi faible des grands nombres",
+            "Loi forte",
+            "faible",
+            "Loi binomiale",
+            "Loi de Poisson",
+            "Loi du poison",
+        ]);
+        let numbers = |query| -> Vec<u16> {
+            let mut numbers: Vec<_> = search(&index, query).iter().map(|p| p.number).collect();
+            numbers.sort();
+            numbers
+        };
+
+        assert_eq!(numbers("\"loi faible\""), vec![0]);
+        assert_eq!(numbers("loi -faible -poisson"), vec![1, 3, 5]);
+        // Exclusions don't match prefixes or typos.
+        assert_eq!(numbers("loi -pois -faible"), vec![1, 3, 4, 5]);
+        assert_eq!(numbers("binomiale OR poisson"), vec![3, 4, 5]);
+        assert_eq!(numbers("faible -(grands nombres)"), vec![2]);
+    }
+
+    #[test]
+    fn phrases() {
+        let index = build_index(&[
+            "Loi faible des grands nombres",
+            "La faible loi",
+            "Inégalité de CS",
+        ]);
+        let numbers = |query| -> Vec<u16> {
+            search(&index, query).iter().map(|p| p.number).collect()
+        };
+
+        // Common words don't separate the words of a phrase.
+        assert_eq!(numbers("\"loi faible grands\""), vec![0]);
+        assert_eq!(numbers("\"faible loi\""), vec![1]);
+        assert!(numbers("\"loi grands\"").is_empty());
+        // Synonyms count as their canonical form.
+        assert_eq!(numbers("\"inégalité de Cauchy-Schwarz\""), vec![2]);
+    }
+
+    #[test]
+    fn phrases_without_positions() {
+        let mut index = index_with_subjects(&[
+            ("maths", "Loi faible des grands nombres"),
+            ("maths", "La faible loi"),
+            ("maths", "Loi de Poisson"),
+        ]);
+        index.word_positions.clear();
+        let index = map_index(&index);
+        let numbers = |query| -> Vec<u16> {
+            let mut numbers: Vec<_> = search(&index, query).iter().map(|p| p.number).collect();
+            numbers.sort();
+            numbers
+        };
+
+        assert_eq!(numbers("\"poisson\""), vec![2]);
+        assert_eq!(numbers("\"loi faible\""), vec![0, 1]);
+    }
+
+    #[test]
+    fn filters() {
+        let index = build_index_with_subjects(&[
+            ("maths", "Interférences de fonctions"),
+            ("physique", "Interférences lumineuses"),
+            ("chimie", "Interférences moléculaires"),
+        ]);
+        let documents = |query| -> Vec<String> {
+            let mut documents: Vec<_> =
+                search(&index, query).into_iter().map(|p| p.document_digest).collect();
+            documents.sort();
+            documents
+        };
+
+        assert_eq!(documents("interférences subject:Physique"), vec!["physique.pdf"]);
+        assert_eq!(documents("interférences -doc:PHYS"), vec!["chimie.pdf", "maths.pdf"]);
+        assert_eq!(
+            documents("interférences chapitre:1 chapitre:3"),
+            vec!["chimie.pdf", "maths.pdf"]
+        );
+        assert!(documents("interférences chapitre:1 subject:physique").is_empty());
+        // Excluded filters are excluded together.
+        assert_eq!(
+            documents("interférences -(subject:physique chapitre:2)"),
+            vec!["chimie.pdf", "maths.pdf"]
+        );
+        assert_eq!(
+            documents("interférences -(subject:physique chapitre:3)"),
+            vec!["chimie.pdf", "maths.pdf", "physique.pdf"]
+        );
+        assert_eq!(
+            documents("interférences -(lumineuses subject:maths)"),
+            vec!["chimie.pdf", "maths.pdf", "physique.pdf"]
+        );
+        assert_eq!(documents("interférences -(-subject:maths)"), vec!["maths.pdf"]);
+    }
+
+    #[test]
+    fn pagination() {
+        let lines = vec!["séries entières"; 7];
+        let index = build_index(&lines);
+        assert_eq!(search(&index, "séries").len(), 5);
+
+        let options = SearchOptions {
+            offset: 5,
+            limit: 5,
+            ..Default::default()
+        };
+        let results = search_with_options(&index, "séries", &options);
+        assert_eq!(results.total, 7);
+        // Pages with the same score keep the order of the index.
+        assert_eq!(
+            results.pages.iter().map(|p| p.number).collect::<Vec<_>>(),
+            vec![5, 6]
+        );
+
+        let options = SearchOptions {
+            max_highlights: 0,
+            ..Default::default()
+        };
+        let results = search_with_options(&index, "séries", &options);
+        assert!(results.pages.iter().all(|p| p.highlights.is_empty()));
+    }
+
+    #[test]
+    fn scoring() {
+        let index = build_index(&[
+            "fonction continue",
+            "fonction dérivable",
+            "principe de Heisenberg",
+            "fonction intégrable",
+        ]);
+        let numbers = |scoring| -> Vec<u16> {
+            let options = SearchOptions {
+                scoring,
+                ..Default::default()
+            };
+            search_with_options(&index, "fonction Heisenberg", &options)
+                .pages
+                .iter()
+                .map(|p| p.number)
+                .collect()
+        };
+
+        // All the lines have the same score without corpus statistics.
+        assert_eq!(numbers(Scoring::Raw), vec![0, 1, 2, 3]);
+        // "heisenberg" is on a single page, so it weighs more than "fonction".
+        assert_eq!(numbers(Scoring::Bm25)[0], 2);
+    }
+
+    #[test]
+    fn explanation() {
+        let index = build_index(&["fonction continue", "principe de Heisenberg"]);
+        let options = SearchOptions {
+            explain: true,
+            ..Default::default()
+        };
+        let results = search_with_options(&index, "heisenbreg", &options);
+        let explanation = results.pages[0].explanation.as_ref().unwrap();
+        assert_eq!(explanation.words.len(), 1);
+        let word = &explanation.words[0];
+        assert_eq!(word.key, "heisenbreg");
+        assert_eq!(word.word, "heisenberg");
+        assert!((word.importance - 0.5).abs() < 0.01);
+        // A transposition counts as two edits.
+        assert_eq!(word.match_multiplier, 0.25);
+        assert!(word.weight > 0.);
+        assert!((explanation.score - word.score.sqrt()).abs() < 1e-6);
+
+        let results = search_with_options(&index, "principe OR heisenberg", &options);
+        let explanation = results.pages[0].explanation.as_ref().unwrap();
+        assert_eq!(explanation.words.len(), 1);
+        assert_eq!(explanation.words[0].key, "principe OR heisenberg");
+
+        assert!(search(&index, "heisenberg")[0].explanation.is_none());
+    }
+
+    #[test]
+    fn term_hits() {
+        let index = build_index(&["loi de Poisson", "loi binomiale"]);
+        let results = search(&index, "loi poisson gamma");
+        assert_eq!(results[0].number, 0);
+        let highlight = &results[0].highlights[0];
+        assert_eq!(highlight.terms, vec!["loi", "poisson"]);
+        let term_hits: Vec<_> =
+            results[0].term_hits.iter().map(|t| (t.term.as_str(), t.count)).collect();
+        // Terms are normalized, and the ones missing from the page are kept.
+        assert_eq!(term_hits, vec![("loi", 1), ("poisson", 1), ("gamm", 0)]);
+
+        let results = search(&index, "\"loi binomiale\" OR poisson");
+        let page = results.iter().find(|p| p.number == 1).unwrap();
+        assert_eq!(page.highlights[0].terms, vec!["loi", "binomial"]);
+        let term_hits: Vec<_> = page.term_hits.iter().map(|t| (t.term.as_str(), t.count)).collect();
+        assert_eq!(term_hits, vec![("loi", 1), ("binomial", 1), ("poisson", 0)]);
+    }
+
+    #[test]
+    fn columns() {
+        // Each page has lines at the given positions.
+        let pages: [&[(i16, i16, &str)]; 2] = [
+            &[(0, 100, "loi binomiale"), (600, 100, "loi de Poisson")],
+            &[(0, 100, "loi binomiale"), (0, 140, "loi de Poisson")],
+        ];
+        let mut index = SearchIndex::new();
+        index.documents.push(Document::default());
+        for lines in pages.iter() {
+            let page_index = push_page(&mut index, 0);
+            for (x, y, line) in lines.iter() {
+                let config = Default::default();
+                push_line(&mut index, page_index, (*x, *y), line, &config, Synonyms::none());
+            }
+        }
+        let index = map_index(&index);
+
+        // The words are at the same height on the first page, but in different columns.
+        let numbers: Vec<_> =
+            search(&index, "binomiale Poisson").iter().map(|p| p.number).collect();
+        assert_eq!(numbers, vec![1, 0]);
+
+        // The best region of the second page covers both lines.
+        let hotspot = search(&index, "binomiale Poisson")[0].hotspot.take().unwrap();
+        assert_eq!(
+            (hotspot.x, hotspot.y, hotspot.width, hotspot.height),
+            (0, 100, 400, 60)
+        );
+        assert!(hotspot.score > 0.);
+
+        let options = SearchOptions {
+            explain: true,
+            tile_size: 100,
+            ..Default::default()
+        };
+        let results = search_with_options(&index, "binomiale Poisson", &options);
+        let explanation = results.pages[0].explanation.as_ref().unwrap();
+        assert_eq!(explanation.tile_size, 100);
+        assert_eq!(explanation.words.len(), 2);
+    }
+
+    #[test]
+    fn snippets() {
+        let index = build_index(&["Inégalités de Bienaymé-Tchebychev.", "Loi de Poisson"]);
+        let results = search(&index, "inégalité tchebychev");
+        let snippet = &results[0].snippets[0];
+        assert_eq!(snippet.text, "Inégalités de Bienaymé-Tchebychev.");
+        let words: Vec<_> = snippet.highlights.iter().map(|r| &snippet.text[r.clone()]).collect();
+        assert_eq!(words, vec!["Inégalités", "Tchebychev"]);
+
+        let options = SearchOptions {
+            max_highlights: 0,
+            ..Default::default()
+        };
+        let results = search_with_options(&index, "poisson", &options);
+        assert!(results.pages[0].snippets.is_empty());
+    }
+
+    #[test]
+    fn breadcrumb() {
+        let mut index = index_with_subjects(&[
+            ("maths", "Espaces préhilbertiens"),
+            ("maths", "Inégalité de Cauchy-Schwarz"),
+            ("maths", "Exercices sur Cauchy"),
+        ]);
+        index.outline.push(OutlineEntry {
+            document_index: 0,
+            parent: None,
+            depth: 0,
+            title: "Espaces préhilbertiens".to_owned(),
+        });
+        index.outline.push(OutlineEntry {
+            document_index: 0,
+            parent: Some(0),
+            depth: 1,
+            title: "Produit scalaire".to_owned(),
+        });
+        index.result_outline = vec![Some(0), Some(1)];
+        let index = map_index(&index);
+
+        let results = search(&index, "inégalité");
+        assert_eq!(results[0].breadcrumb, vec!["Espaces préhilbertiens", "Produit scalaire"]);
+        let results = search(&index, "préhilbertiens");
+        assert_eq!(results[0].breadcrumb, vec!["Espaces préhilbertiens"]);
+        // The last line has no outline entry.
+        let results = search(&index, "exercices");
+        assert!(results[0].breadcrumb.is_empty());
+    }
+
+    #[test]
+    fn word_boxes() {
+        let mut index = SearchIndex::new();
+        index.documents.push(Document::default());
+        let page_index = push_page(&mut index, 0);
+        let line = "loi de Poisson";
+        push_line(&mut index, page_index, (0, 100), line, &Default::default(), Synonyms::none());
+        // Only "poisson" has a box.
+        index.word_boxes.push(WordBox {
+            result_index: 0,
+            word: "poisson".to_owned(),
+            x: 250,
+            width: 150,
+        });
+        let index = map_index(&index);
+
+        let rects = |query| -> Vec<_> {
+            search(&index, query)[0]
+                .highlights
+                .iter()
+                .map(|h| (h.x, h.y, h.width, h.height, h.terms.clone()))
+                .collect()
+        };
+        // Prefixes are highlighted with the box of the whole word.
+        assert_eq!(rects("poiss"), vec![(250, 100, 150, 20, vec!["poiss".to_owned()])]);
+        // Lines fall back to a single highlight when a matched word has no box.
+        assert_eq!(
+            rects("loi poisson"),
+            vec![(0, 100, 400, 20, vec!["loi".to_owned(), "poisson".to_owned()])]
+        );
+    }
+
+    #[test]
+    fn synonym_expansion() {
+        let lines = [
+            "Inégalité de Cauchy-Schwarz",
+            "Cas de CS",
+            "Variable aléatoire discrète",
+            "Une VA",
+        ];
+        let lines: Vec<_> = lines.iter().map(|l| ("maths", *l)).collect();
+        let index =
+            map_index(&index_with_normalization(&lines, &Default::default(), Synonyms::none()));
+        let synonyms =
+            Arc::new(Synonyms::parse("\"cauchy schwarz\" cs\n\"variable aléatoire\" va"));
+        let numbers = |query, expand_synonyms| -> Vec<u16> {
+            let options = SearchOptions {
+                scoring: Scoring::Raw,
+                synonyms: synonyms.clone(),
+                expand_synonyms,
+                ..Default::default()
+            };
+            search_with_options(&index, query, &options)
+                .pages
+                .iter()
+                .map(|p| p.number)
+                .collect()
+        };
+
+        // The words as typed rank before their synonyms.
+        assert_eq!(numbers("cauchy schwarz", true), vec![0, 1]);
+        assert_eq!(numbers("cs", true), vec![1, 0]);
+        assert_eq!(numbers("va", true), vec![3, 2]);
+        assert_eq!(numbers("\"cs\"", true), vec![1]);
+
+        let options = SearchOptions {
+            synonyms,
+            expand_synonyms: true,
+            ..Default::default()
+        };
+        let page = search_with_options(&index, "cs", &options).pages.remove(1);
+        assert_eq!(page.term_hits[0].term, "cs");
+        assert_eq!(page.term_hits[0].count, 1);
+        assert_eq!(page.snippets[0].highlights, vec![15..21, 22..29]);
+    }
+
+    #[test]
+    fn languages() {
+        let lines = [("physics", "The conservation of energy")];
+        let index = map_index(&index_with_normalization(
+            &lines,
+            Language::English.profile(),
+            Synonyms::none(),
+        ));
+        let options = SearchOptions {
+            language: Language::English,
+            ..Default::default()
+        };
+        let pages = search_with_options(&index, "energies OF the Conservation", &options).pages;
+        assert_eq!(pages.len(), 1);
+        let terms: Vec<_> = pages[0].term_hits.iter().map(|t| t.term.as_str()).collect();
+        assert_eq!(terms, vec!["energi", "conserv"]);
+        assert_eq!(pages[0].snippets[0].highlights, vec![4..16, 20..26]);
+
+        // English stop words are not indexed, even though a French query searches them.
+        assert!(search(&index, "the").is_empty());
+    }
 }
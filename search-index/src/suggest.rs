@@ -0,0 +1,161 @@
+//! "Did you mean" suggestions for queries that return no pages.
+
+use crate::{
+    fuzzy::{edit_distance, max_edit_distance},
+    mapped::MappedSearchIndex,
+    normalize::{tokenize, NormalizerConfig},
+    query::{parse, Query},
+    synonyms::Synonyms,
+};
+
+/// Returns the indexed word that is closest to `word`, preferring the most frequent one among
+/// words at the same distance. The budget is one edit larger than what the search tolerates.
+fn closest_word(search_index: &MappedSearchIndex, word: &str) -> Option<String> {
+    let max_distance = max_edit_distance(word) + 1;
+    search_index
+        .words_within_distance(word, max_distance)?
+        .map(|(candidate, matches)| {
+            let distance = edit_distance(word, &candidate);
+            (candidate, distance, matches.len())
+        })
+        .min_by(|(_, distance_a, frequency_a), (_, distance_b, frequency_b)| {
+            distance_a
+                .cmp(distance_b)
+                .then_with(|| frequency_b.cmp(frequency_a))
+        })
+        .map(|(candidate, _, _)| candidate)
+}
+
+/// Returns how an indexed word is written in a line that contains it, or the word itself if the
+/// index has no text.
+fn surface_form(
+    search_index: &MappedSearchIndex,
+    word: &str,
+    config: &NormalizerConfig,
+    synonyms: &Synonyms,
+) -> String {
+    let lines = search_index.postings(word).into_iter().flatten().take(10);
+    for m in lines {
+        let Some(text) = search_index.result_text(m.result_index as usize) else {
+            continue;
+        };
+        let tokens = tokenize(&text, config, synonyms);
+        let token = tokens
+            .iter()
+            .find(|t| t.word == word && !t.text.contains(char::is_whitespace));
+        if let Some(token) = token {
+            return token.text.to_owned();
+        }
+    }
+    word.to_owned()
+}
+
+/// Replaces the words of `text` that are not in the index, returning whether any was replaced.
+fn correct_text(
+    search_index: &MappedSearchIndex,
+    text: &mut String,
+    config: &NormalizerConfig,
+    synonyms: &Synonyms,
+) -> bool {
+    let tokens = tokenize(text, config, synonyms);
+    let mut corrected = String::new();
+    let mut end = 0;
+    for (i, token) in tokens.iter().enumerate() {
+        // The words of a synonym share their text and are not typos.
+        let is_synonym = tokens
+            .iter()
+            .enumerate()
+            .any(|(j, t)| j != i && t.offsets == token.offsets);
+        if is_synonym || search_index.words_with_prefix(&token.word).next().is_some() {
+            continue;
+        }
+        if let Some(closest) = closest_word(search_index, &token.word) {
+            corrected.push_str(&text[end..token.offsets.start]);
+            corrected.push_str(&surface_form(search_index, &closest, config, synonyms));
+            end = token.offsets.end;
+        }
+    }
+    if end == 0 {
+        return false;
+    }
+    corrected.push_str(&text[end..]);
+    *text = corrected;
+    true
+}
+
+/// Replaces the misspelled words and phrases of a query, returning whether any was replaced.
+/// Exclusions and filters are kept as typed.
+fn correct(
+    search_index: &MappedSearchIndex,
+    query: &mut Query,
+    config: &NormalizerConfig,
+    synonyms: &Synonyms,
+) -> bool {
+    match query {
+        Query::Words(text) | Query::Phrase(text) => {
+            correct_text(search_index, text, config, synonyms)
+        }
+        Query::Or(clauses) | Query::Group(clauses) => {
+            let mut replaced = false;
+            for clause in clauses.iter_mut() {
+                replaced |= correct(search_index, clause, config, synonyms);
+            }
+            replaced
+        }
+        Query::Filter(_, _) | Query::Not(_) => false,
+    }
+}
+
+/// Proposes an alternative to `query` where the words that are not in the index are replaced by
+/// the closest indexed words. Returns `None` if there is nothing to replace or no replacement.
+pub fn suggest(
+    search_index: &MappedSearchIndex,
+    query: &str,
+    config: &NormalizerConfig,
+    synonyms: &Synonyms,
+) -> Option<String> {
+    let mut query = parse(query);
+    if correct(search_index, &mut query, config, synonyms) {
+        Some(query.to_string())
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{search::tests::build_index, synonyms::Synonyms};
+
+    use super::suggest;
+
+    #[test]
+    fn suggestions() {
+        let index = build_index(&[
+            "Loi de Poisson",
+            "loi de Poisson",
+            "loi de Poisson",
+            "loi",
+            "loi",
+            "boisson",
+            "Inégalité de Markov",
+        ]);
+        let suggest = |query| suggest(&index, query, &Default::default(), &Synonyms::builtin());
+
+        // "poisson" and "boisson" are both one edit away, but "poisson" is more frequent.
+        assert_eq!(suggest("loi de Xoisson").as_deref(), Some("loi de Poisson"));
+        assert_eq!(suggest("loi de Poisson"), None);
+        assert_eq!(suggest("xyzxyzxyz"), None);
+        // Replacements are written as in the documents, not as stems.
+        assert_eq!(suggest("ingalité").as_deref(), Some("Inégalité"));
+        assert_eq!(
+            suggest("\"loi de Xoisson\" OR boison").as_deref(),
+            Some("\"loi de Poisson\" OR boisson")
+        );
+        // Exclusions and filters are kept.
+        assert_eq!(suggest("loi -Xoisson"), None);
+        assert_eq!(
+            suggest("Xoisson -Xoisson chapitre:Xoisson").as_deref(),
+            Some("Poisson -Xoisson chapter:Xoisson")
+        );
+    }
+}
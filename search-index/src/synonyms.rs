@@ -0,0 +1,406 @@
+//! Synonyms that normalization replaces with a canonical form, so that e.g. "cs" and
+//! "Cauchy-Schwarz" match each other.
+//!
+//! A synonym file has a group of synonyms per line, separated by spaces, the first one being the
+//! canonical form. Synonyms made of several words are quoted, and `\` escapes a character:
+//!
+//! ```text
+//! "cauchy schwarz" cs
+//! exponentielle exp
+//! ```
+//!
+//! Synonyms are replaced in a single pass that prefers the longest match, so that "variable
+//! aléatoire discrète" is not replaced as "variable aléatoire". Replacements are not chained.
+//!
+//! The table is stemmed in each language, and synonyms are found with the stems of the language
+//! of the text.
+//!
+//! The built-in table comes from `src/synonyms.txt`. A table loaded at runtime must be the same
+//! for the generator and the search, which is why its fingerprint is stored in the index.
+//!
+//! Alternatively, the generator can record `QUERY_SYNONYMS` and index the words as they are, so
+//! that the search expands the query instead and the table can change without a rebuild.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fs, io,
+    ops::Range,
+    path::Path,
+    sync::{Arc, OnceLock},
+};
+
+use rust_stemmers::Stemmer;
+
+use crate::normalize::{Language, NormalizerConfig};
+
+/// Key of the generator setting that records the fingerprint of the synonyms of an index.
+pub const SYNONYMS_SETTING: &str = "synonyms";
+
+/// Value of `SYNONYMS_SETTING` for indexes whose words are not canonicalized, where synonyms are
+/// applied at query time.
+pub const QUERY_SYNONYMS: &str = "query";
+
+/// Splits a line of a synonym file into synonyms.
+fn parse_words(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut current = String::new();
+    let mut quote = false;
+    let mut escape = false;
+    for c in line.chars() {
+        if escape {
+            current.push(c);
+            escape = false;
+        } else if c == '"' {
+            quote = !quote;
+        } else if c == '\\' {
+            escape = true;
+        } else if c == ' ' && !quote {
+            if !current.is_empty() {
+                words.push(std::mem::take(&mut current));
+            }
+        } else {
+            current.push(c);
+        }
+    }
+    if !current.is_empty() {
+        words.push(current);
+    }
+    words
+}
+
+/// Stems the words of a synonym like normalization does, without removing common words.
+fn stem_synonym(config: &NormalizerConfig, stemmer: &Stemmer, synonym: &str) -> String {
+    deunicode::deunicode(synonym)
+        .split_whitespace()
+        .map(|w| config.stem(stemmer, &w.to_lowercase()))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// State of the automaton that finds synonyms in a sequence of words, which is a trie of the
+/// synonyms with failure links (Aho-Corasick).
+#[derive(Debug, Default)]
+struct Node {
+    /// Next state for each word, identified by its index in `Synonyms::words`.
+    children: HashMap<u32, usize>,
+    /// State of the longest proper suffix of the words leading here that is also in the trie.
+    fail: usize,
+    /// The synonym or canonical form that ends here, if any.
+    pattern: Option<Pattern>,
+    /// Closest state on the failure chain where a pattern ends, if any.
+    output: Option<usize>,
+}
+
+/// A sequence of words that the automaton finds.
+#[derive(Clone, Copy, Debug)]
+struct Pattern {
+    group: usize,
+    /// Number of words.
+    len: usize,
+    /// Whether this is the canonical form of the group rather than one of its synonyms.
+    canonical: bool,
+}
+
+/// Builds the automaton of the synonyms and canonical forms of `groups`, whose words are
+/// numbered in `words`.
+fn build_automaton(groups: &[(String, Vec<String>)]) -> (HashMap<String, u32>, Vec<Node>) {
+    let mut words = HashMap::new();
+    let mut nodes = vec![Node::default()];
+    let synonyms = groups
+        .iter()
+        .enumerate()
+        .flat_map(|(group, (_, synonyms))| synonyms.iter().map(move |s| (group, s, false)));
+    let canonical_forms = groups
+        .iter()
+        .enumerate()
+        .map(|(group, (canonical, _))| (group, canonical, true));
+    // If a sequence of words is in several groups, the first one wins, and synonyms win over
+    // canonical forms so that canonicalization doesn't depend on them.
+    for (group, pattern, canonical) in synonyms.chain(canonical_forms) {
+        let mut node = 0;
+        let mut len = 0;
+        for word in pattern.split(' ') {
+            let next_id = words.len() as u32;
+            let id = *words.entry(word.to_owned()).or_insert(next_id);
+            node = match nodes[node].children.get(&id) {
+                Some(&child) => child,
+                None => {
+                    nodes.push(Node::default());
+                    let child = nodes.len() - 1;
+                    nodes[node].children.insert(id, child);
+                    child
+                }
+            };
+            len += 1;
+        }
+        nodes[node].pattern.get_or_insert(Pattern {
+            group,
+            len,
+            canonical,
+        });
+    }
+
+    // Breadth-first, so that failure links point to states that are already complete. The
+    // children of the root fail to the root, which is the default.
+    let mut queue: VecDeque<usize> = nodes[0].children.values().copied().collect();
+    while let Some(node) = queue.pop_front() {
+        let children: Vec<(u32, usize)> =
+            nodes[node].children.iter().map(|(&w, &c)| (w, c)).collect();
+        for (word, child) in children {
+            let mut fail = nodes[node].fail;
+            let fail = loop {
+                if let Some(&next) = nodes[fail].children.get(&word) {
+                    break next;
+                }
+                if fail == 0 {
+                    break 0;
+                }
+                fail = nodes[fail].fail;
+            };
+            nodes[child].fail = fail;
+            nodes[child].output = match nodes[fail].pattern {
+                Some(_) => Some(fail),
+                None => nodes[fail].output,
+            };
+            queue.push_back(child);
+        }
+    }
+    (words, nodes)
+}
+
+/// A table of synonyms stemmed in one language.
+#[derive(Debug)]
+struct StemmedSynonyms {
+    /// The canonical form of each group and the synonyms that are replaced by it.
+    groups: Vec<(String, Vec<String>)>,
+    /// The words that appear in synonyms.
+    words: HashMap<String, u32>,
+    /// The states of the automaton, starting with the root.
+    nodes: Vec<Node>,
+}
+
+/// A table of synonyms, stemmed.
+#[derive(Debug)]
+pub struct Synonyms {
+    /// The table stemmed in each language of `Language::ALL`. Groups are in the same order in
+    /// each of them.
+    tables: Vec<StemmedSynonyms>,
+}
+
+impl Synonyms {
+    /// Parses a synonym file. Lines with less than two synonyms are ignored.
+    pub fn parse(text: &str) -> Self {
+        let lines: Vec<Vec<String>> = text
+            .lines()
+            .map(parse_words)
+            .filter(|synonyms| synonyms.len() >= 2)
+            .collect();
+        let tables = Language::ALL
+            .iter()
+            .map(|language| {
+                let config = language.profile();
+                let stemmer = config.stemmer();
+                let groups: Vec<_> = lines
+                    .iter()
+                    .map(|synonyms| {
+                        let mut stems = synonyms.iter().map(|s| stem_synonym(config, &stemmer, s));
+                        let canonical = stems.next().unwrap();
+                        (canonical, stems.collect())
+                    })
+                    .collect();
+                let (words, nodes) = build_automaton(&groups);
+                StemmedSynonyms {
+                    groups,
+                    words,
+                    nodes,
+                }
+            })
+            .collect();
+        Self { tables }
+    }
+
+    fn table(&self, language: Language) -> &StemmedSynonyms {
+        let index = Language::ALL.iter().position(|&l| l == language).unwrap();
+        &self.tables[index]
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Self::parse(&fs::read_to_string(path)?))
+    }
+
+    /// Returns an empty table.
+    pub fn none() -> &'static Self {
+        static NONE: OnceLock<Synonyms> = OnceLock::new();
+        NONE.get_or_init(|| Self::parse(""))
+    }
+
+    /// Returns the table built into the library.
+    pub fn builtin() -> Arc<Self> {
+        static BUILTIN: OnceLock<Arc<Synonyms>> = OnceLock::new();
+        BUILTIN
+            .get_or_init(|| Arc::new(Self::parse(include_str!("synonyms.txt"))))
+            .clone()
+    }
+
+    /// Identifies the content of the table, once stemmed, to check that an index was built with
+    /// the same table as the one used to search it.
+    pub fn fingerprint(&self) -> String {
+        let mut hasher = crc32fast::Hasher::new();
+        let groups = self.tables.iter().flat_map(|table| table.groups.iter());
+        for (canonical, synonyms) in groups {
+            hasher.update(canonical.as_bytes());
+            for s in synonyms {
+                hasher.update(b"\t");
+                hasher.update(s.as_bytes());
+            }
+            hasher.update(b"\n");
+        }
+        format!("{:08x}", hasher.finalize())
+    }
+
+    /// Finds the longest non-overlapping synonyms in a sequence of words stemmed in `language`,
+    /// and their canonical forms if `canonical` is set. Returns the range of words and the group
+    /// of each.
+    pub(crate) fn find(
+        &self,
+        language: Language,
+        words: &[String],
+        canonical: bool,
+    ) -> Vec<(Range<usize>, usize)> {
+        let table = self.table(language);
+        // Group and length of the longest pattern that starts at each word.
+        let mut longest: Vec<Option<(usize, usize)>> = vec![None; words.len()];
+        let mut node = 0;
+        for (i, word) in words.iter().enumerate() {
+            let id = table.words.get(word.as_str());
+            node = loop {
+                match id.and_then(|id| table.nodes[node].children.get(id)) {
+                    Some(&next) => break next,
+                    None if node == 0 => break 0,
+                    None => node = table.nodes[node].fail,
+                }
+            };
+            let mut found = match table.nodes[node].pattern {
+                Some(_) => Some(node),
+                None => table.nodes[node].output,
+            };
+            while let Some(n) = found {
+                let pattern = table.nodes[n].pattern.unwrap();
+                let start = i + 1 - pattern.len;
+                if (canonical || !pattern.canonical)
+                    && longest[start].is_none_or(|(_, len)| len < pattern.len)
+                {
+                    longest[start] = Some((pattern.group, pattern.len));
+                }
+                found = table.nodes[n].output;
+            }
+        }
+
+        let mut matches = Vec::new();
+        let mut i = 0;
+        while i < words.len() {
+            match longest[i] {
+                Some((group, len)) => {
+                    matches.push((i..(i + len), group));
+                    i += len;
+                }
+                None => i += 1,
+            }
+        }
+        matches
+    }
+
+    /// Returns the words of the canonical form and of each synonym of a group, stemmed in
+    /// `language`.
+    pub(crate) fn group_words(&self, language: Language, group: usize) -> Vec<Vec<String>> {
+        let (canonical, synonyms) = &self.table(language).groups[group];
+        [canonical]
+            .into_iter()
+            .chain(synonyms)
+            .map(|s| s.split(' ').map(str::to_owned).collect())
+            .collect()
+    }
+
+    /// Returns the canonical form of a group, stemmed in `language`.
+    pub(crate) fn canonical(&self, language: Language, group: usize) -> &str {
+        &self.table(language).groups[group].0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::normalize::{normalize, normalize_with_synonyms, Language};
+
+    use super::{parse_words, Synonyms};
+
+    #[test]
+    fn parsing() {
+        assert_eq!(
+            parse_words(r#""sous espace" "sous \"espace\" vectoriel"  sev"#),
+            vec!["sous espace", "sous \"espace\" vectoriel", "sev"]
+        );
+
+        let synonyms = Synonyms::parse("\"théorème valeurs intermédiaires\" tvi\nseul\n");
+        assert_eq!(
+            synonyms.table(Language::French).groups,
+            &[("theorem valeur intermediair".to_owned(), vec!["tvi".to_owned()])]
+        );
+        assert_ne!(synonyms.fingerprint(), Synonyms::builtin().fingerprint());
+        assert_eq!(
+            synonyms.fingerprint(),
+            Synonyms::parse("\"Théorème valeurs intermédiaires\"   TVI").fingerprint()
+        );
+    }
+
+    /// Normalizes `text` with the synonyms of `table`.
+    fn canonicalize(table: &str, text: &str) -> Vec<String> {
+        normalize_with_synonyms(text, &Synonyms::parse(table))
+    }
+
+    /// Normalizes `text` without synonyms.
+    fn words(text: &str) -> Vec<String> {
+        canonicalize("", text)
+    }
+
+    #[test]
+    fn longest_match() {
+        for table in [
+            "vad \"variable aléatoire discrète\"\nva \"variable aléatoire\"",
+            "va \"variable aléatoire\"\nvad \"variable aléatoire discrète\"",
+        ] {
+            assert_eq!(canonicalize(table, "variable aléatoire discrète"), vec!["vad"]);
+            assert_eq!(canonicalize(table, "variable aléatoire continue"), words("va continue"));
+            assert_eq!(
+                canonicalize(table, "variable variable aléatoire discrète"),
+                words("variable vad")
+            );
+            assert_eq!(canonicalize(table, "variable discrète"), words("variable discrète"));
+        }
+    }
+
+    #[test]
+    fn overlapping_synonyms() {
+        // The leftmost synonym wins.
+        let table = "sev \"sous espace\"\nev \"espace vectoriel\"";
+        assert_eq!(canonicalize(table, "sous espace vectoriel"), words("sev vectoriel"));
+        assert_eq!(canonicalize(table, "un espace vectoriel"), vec!["ev"]);
+
+        // A synonym that ends a partial match of a longer one.
+        let table = "vad \"variable aléatoire discrète\"\nac \"aléatoire continue\"";
+        assert_eq!(canonicalize(table, "variable aléatoire continue"), words("variable ac"));
+    }
+
+    #[test]
+    fn languages() {
+        let synonyms = Synonyms::parse("gaussian \"normal distributions\"");
+        let english = Language::English.profile();
+        assert_eq!(normalize("a normal distribution", english, &synonyms), vec!["gaussian"]);
+    }
+
+    #[test]
+    fn no_chaining() {
+        let table = "exponentielle exp\nexp expo";
+        assert_eq!(canonicalize(table, "expo"), words("exp"));
+        assert_eq!(canonicalize(table, "exp expo"), words("exponentielle exp"));
+    }
+}
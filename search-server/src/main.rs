@@ -1,10 +1,12 @@
-use std::{
-    collections::{BTreeMap, HashMap},
-    env,
-    fs::File,
-};
+use std::{env, fs, sync::Arc};
 
-use search_index::index::SearchIndex;
+use search_index::{
+    index::{IndexError, SearchIndex},
+    mapped::MappedSearchIndex,
+    normalize::Language,
+    search::{Highlight, Hotspot, PageExplanation, Scoring, SearchOptions, Snippet, TermHits},
+    synonyms::Synonyms,
+};
 use serde::Serialize;
 
 use http_server::{HttpServer, Response};
@@ -17,6 +19,138 @@ struct Rect {
     y: i16,
     width: u16,
     height: u16,
+    /// The query words on the line.
+    terms: Vec<String>,
+}
+
+impl From<Highlight> for Rect {
+    fn from(h: Highlight) -> Self {
+        Rect {
+            x: h.x,
+            y: h.y,
+            width: h.width,
+            height: h.height,
+            terms: h.terms,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct TermCount {
+    term: String,
+    count: usize,
+}
+
+impl From<TermHits> for TermCount {
+    fn from(t: TermHits) -> Self {
+        TermCount {
+            term: t.term,
+            count: t.count,
+        }
+    }
+}
+
+/// A part of a snippet, highlighted if it is a matched word.
+#[derive(Serialize)]
+struct Fragment {
+    text: String,
+    highlight: bool,
+}
+
+/// Splits a snippet at the boundaries of its highlights, so that clients don't have to deal with
+/// byte offsets.
+fn fragments(snippet: Snippet) -> Vec<Fragment> {
+    let mut fragments = Vec::new();
+    let mut end = 0;
+    for range in snippet.highlights {
+        if range.start > end {
+            fragments.push(Fragment {
+                text: snippet.text[end..range.start].to_owned(),
+                highlight: false,
+            });
+        }
+        end = range.end;
+        fragments.push(Fragment {
+            text: snippet.text[range].to_owned(),
+            highlight: true,
+        });
+    }
+    if end < snippet.text.len() {
+        fragments.push(Fragment {
+            text: snippet.text[end..].to_owned(),
+            highlight: false,
+        });
+    }
+    fragments
+}
+
+#[derive(Serialize)]
+struct Region {
+    x: i16,
+    y: i16,
+    width: u16,
+    height: u16,
+    score: f32,
+}
+
+impl From<Hotspot> for Region {
+    fn from(h: Hotspot) -> Self {
+        Region {
+            x: h.x,
+            y: h.y,
+            width: h.width,
+            height: h.height,
+            score: h.score,
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct WordExplanation {
+    key: String,
+    word: String,
+    line: Rect,
+    importance: f32,
+    match_multiplier: f32,
+    weight: f32,
+    distance_factor: f32,
+    score: f32,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Explanation {
+    score: f32,
+    tile_x: u32,
+    tile_y: u32,
+    tile_size: u32,
+    words: Vec<WordExplanation>,
+}
+
+impl From<PageExplanation> for Explanation {
+    fn from(e: PageExplanation) -> Self {
+        Explanation {
+            score: e.score,
+            tile_x: e.tile_x,
+            tile_y: e.tile_y,
+            tile_size: e.tile_size,
+            words: e
+                .words
+                .into_iter()
+                .map(|w| WordExplanation {
+                    key: w.key,
+                    word: w.word,
+                    line: w.line.into(),
+                    importance: w.importance,
+                    match_multiplier: w.match_multiplier,
+                    weight: w.weight,
+                    distance_factor: w.distance_factor,
+                    score: w.score,
+                })
+                .collect(),
+        }
+    }
 }
 
 #[derive(Serialize)]
@@ -29,6 +163,80 @@ struct Page {
     width: u16,
     height: u16,
     rects: Vec<Rect>,
+    /// The number of matching lines for each word of the query, including missing words.
+    term_hits: Vec<TermCount>,
+    /// The text of the highlighted lines, empty if the index doesn't store it.
+    snippets: Vec<Vec<Fragment>>,
+    /// The titles of the sections that contain the best match, outermost first.
+    breadcrumb: Vec<String>,
+    /// The region of the page that matches best.
+    hotspot: Option<Region>,
+    /// Only returned by the `/explain/` endpoint.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    explanation: Option<Explanation>,
+}
+
+#[derive(Serialize)]
+struct SearchResponse {
+    pages: Vec<Page>,
+    /// Number of pages that match the query, including the ones that were not returned.
+    total: usize,
+    /// An alternative query, when this one returned no pages.
+    suggestion: Option<String>,
+}
+
+/// Upper bound on the `limit` parameter, so that a single request can't make the server build
+/// every matching page.
+const MAX_LIMIT: usize = 100;
+
+/// Bounds on the `tile` parameter, since the number of tiles of a page grows with the inverse
+/// square of their size.
+const MIN_TILE_SIZE: usize = 16;
+const MAX_TILE_SIZE: usize = 1024;
+/// Upper bound on the `radius` parameter, beyond which all the lines of a page count as close.
+const MAX_HOTSPOT_RADIUS: usize = 2000;
+
+/// Splits a request URL of the form `/<query>?offset=5&limit=10` into the decoded query and the
+/// search options. URLs starting with `/explain/` also explain the scores of the pages.
+fn parse_url(url: &str) -> (String, SearchOptions) {
+    let url = url.strip_prefix('/').unwrap_or(url);
+    let mut options = SearchOptions::default();
+    let url = match url.strip_prefix("explain/") {
+        Some(url) => {
+            options.explain = true;
+            url
+        }
+        None => url,
+    };
+    let (query, parameters) = url.split_once('?').unwrap_or((url, ""));
+    for parameter in parameters.split('&') {
+        let (name, value) = match parameter.split_once('=') {
+            Some(p) => p,
+            None => continue,
+        };
+        match (name, value.parse::<usize>()) {
+            ("offset", Ok(v)) => options.offset = v,
+            ("limit", Ok(v)) => options.limit = v.min(MAX_LIMIT),
+            ("highlights", Ok(v)) => options.max_highlights = v,
+            ("tile", Ok(v)) => options.tile_size = v.clamp(MIN_TILE_SIZE, MAX_TILE_SIZE) as u32,
+            ("radius", Ok(v)) => options.hotspot_radius = v.clamp(1, MAX_HOTSPOT_RADIUS) as f32,
+            ("scoring", _) => {
+                if let Some(scoring) = Scoring::parse(value) {
+                    options.scoring = scoring;
+                }
+            }
+            ("lang", _) => {
+                if let Some(language) = Language::parse(value) {
+                    options.language = language;
+                }
+            }
+            _ => {}
+        }
+    }
+    let query = urlencoding::decode(query)
+        .map(|q| q.into_owned())
+        .unwrap_or_default();
+    (query, options)
 }
 
 fn main() {
@@ -38,14 +246,46 @@ fn main() {
     let cors_origin =
         env::var("CORS_ORIGIN").unwrap_or_else(|_| "http://localhost:8000".to_owned());
 
-    let mut search_index_file = File::open(search_index_path).unwrap();
-    let search_index = SearchIndex::deserialize(&mut search_index_file).unwrap();
+    let search_index = match MappedSearchIndex::open(&search_index_path) {
+        Ok(i) => i,
+        Err(IndexError::WrongMagic) => {
+            eprintln!(
+                "warning: {} has no header, reading it with the legacy layout; regenerate it",
+                search_index_path
+            );
+            // Convert the index to the current layout in memory.
+            let legacy_bytes = fs::read(&search_index_path).unwrap();
+            let legacy_index = SearchIndex::deserialize_headerless(&mut &legacy_bytes[..]).unwrap();
+            let mut bytes = Vec::new();
+            legacy_index.serialize(&mut bytes).unwrap();
+            MappedSearchIndex::from_bytes(bytes).unwrap()
+        }
+        Err(e) => panic!("failed to load search index at {}: {}", search_index_path, e),
+    };
+
+    // Queries must be normalized with the synonyms that the index was built with, otherwise the
+    // words that have synonyms don't match, unless the index leaves synonyms to the queries.
+    let synonyms = match env::var_os("SYNONYMS_FILE") {
+        Some(path) => Arc::new(Synonyms::load(&path).unwrap_or_else(|e| {
+            panic!("failed to load synonyms at {}: {}", path.to_string_lossy(), e)
+        })),
+        None => Synonyms::builtin(),
+    };
+    if let Some(mismatch) = search_index.synonyms_mismatch(&synonyms) {
+        eprintln!("warning: {}", mismatch);
+    }
+    let expand_synonyms = search_index.has_query_synonyms();
 
     let server = HttpServer::bind(addr).unwrap();
     server
         .serve(|req| {
-            let query = urlencoding::decode(&req.url[1..]).unwrap();
-            let pages: Vec<_> = search_index::search::search(&search_index, &query)
+            let (query, mut options) = parse_url(&req.url);
+            options.synonyms = synonyms.clone();
+            options.expand_synonyms = expand_synonyms;
+            let results =
+                search_index::search::search_with_options(&search_index, &query, &options);
+            let pages: Vec<_> = results
+                .pages
                 .into_iter()
                 .map(|p| Page {
                     document_name: p.document_digest,
@@ -54,15 +294,37 @@ fn main() {
                     rendered_jpeg: p.rendered_jpeg,
                     width: p.width,
                     height: p.height,
-                    rects: p.highlights.into_iter().map(|h| Rect {
-                        x: h.x,
-                        y: h.y,
-                        width: h.width,
-                        height: h.height,
-                    }).collect(),
+                    rects: p.highlights.into_iter().map(Rect::from).collect(),
+                    term_hits: p.term_hits.into_iter().map(TermCount::from).collect(),
+                    snippets: p.snippets.into_iter().map(fragments).collect(),
+                    breadcrumb: p.breadcrumb,
+                    hotspot: p.hotspot.map(Region::from),
+                    explanation: p.explanation.map(Explanation::from),
                 })
                 .collect();
-            let body: Vec<u8> = serde_json::to_string(&pages).unwrap().into();
+            let suggestion = if results.total == 0 {
+                // Suggestions are indexed words, so they are normalized like the index.
+                let index_synonyms = if expand_synonyms {
+                    Synonyms::none()
+                } else {
+                    &synonyms
+                };
+                search_index::suggest::suggest(
+                    &search_index,
+                    &query,
+                    options.language.profile(),
+                    index_synonyms,
+                )
+            } else {
+                None
+            };
+            let body: Vec<u8> = serde_json::to_string(&SearchResponse {
+                pages,
+                total: results.total,
+                suggestion,
+            })
+                .unwrap()
+                .into();
             let mut headers = vec![("Content-Length".to_string(), body.len().to_string())];
             if !cors_origin.is_empty() {
                 headers.push((
@@ -78,3 +340,65 @@ fn main() {
         })
         .unwrap();
 }
+
+#[cfg(test)]
+mod tests {
+    use search_index::{
+        normalize::Language,
+        search::{Scoring, Snippet},
+    };
+
+    use super::{fragments, parse_url, MAX_HOTSPOT_RADIUS, MAX_LIMIT, MIN_TILE_SIZE};
+
+    #[test]
+    fn url_parameters() {
+        let (query, options) = parse_url("/loi%20de%20Poisson%3F?offset=10&limit=1000&highlights=3");
+        assert_eq!(query, "loi de Poisson?");
+        assert_eq!(options.offset, 10);
+        assert_eq!(options.limit, MAX_LIMIT);
+        assert_eq!(options.max_highlights, 3);
+        assert_eq!(options.scoring, Scoring::Raw);
+        assert_eq!(options.language, Language::French);
+        assert_eq!(options.tile_size, 64);
+
+        let (_, options) = parse_url("/loi?tile=128&radius=50");
+        assert_eq!(options.tile_size, 128);
+        assert_eq!(options.hotspot_radius, 50.);
+        let (_, options) = parse_url("/loi?tile=1&radius=1000000");
+        assert_eq!(options.tile_size, MIN_TILE_SIZE as u32);
+        assert_eq!(options.hotspot_radius, MAX_HOTSPOT_RADIUS as f32);
+
+        let (query, options) = parse_url("/s%C3%A9ries?limit=x&offset&scoring=bm25&lang=en");
+        assert_eq!(query, "séries");
+        assert_eq!(options.offset, 0);
+        assert_eq!(options.limit, 5);
+        assert_eq!(options.scoring, Scoring::Bm25);
+        assert!(!options.explain);
+        assert_eq!(options.language, Language::English);
+
+        let (query, options) = parse_url("/explain/loi%20faible?limit=1");
+        assert_eq!(query, "loi faible");
+        assert_eq!(options.limit, 1);
+        assert!(options.explain);
+    }
+
+    #[test]
+    fn snippet_fragments() {
+        let snippet = Snippet {
+            text: "Loi de Poisson".to_owned(),
+            highlights: vec![0..3, 7..14],
+        };
+        let fragments: Vec<_> = fragments(snippet)
+            .into_iter()
+            .map(|f| (f.text, f.highlight))
+            .collect();
+        assert_eq!(
+            fragments,
+            vec![
+                ("Loi".to_owned(), true),
+                (" de ".to_owned(), false),
+                ("Poisson".to_owned(), true),
+            ]
+        );
+    }
+}